@@ -1,7 +1,7 @@
 use lib_ray_tracer::{
     camera::{self, Camera},
     geometry::Sphere,
-    material::Material,
+    material::{Dielectric, Lambertian, Metal},
     world::World,
     Renderer,
 };
@@ -12,6 +12,7 @@ use speedy2d::shape::Rectangle;
 use speedy2d::window::{VirtualKeyCode, WindowHandler, WindowHelper};
 use speedy2d::Graphics2D;
 use speedy2d::Window;
+use std::sync::Arc;
 
 const UPSCALE: u32 = 5;
 
@@ -33,28 +34,28 @@ pub fn run() {
 
     let mut world = World::empty();
 
-    let ground_mat = Material::Lambertian(Vector3::new(0.5, 0.5, 0.5));
+    let ground_mat = Arc::new(Lambertian::new(Vector3::new(0.5, 0.5, 0.5)));
     world.add(Sphere::stationary(
         Point3::new(0.0, -1000.0, 0.0),
         1000.0,
         ground_mat,
     ));
 
-    let material1 = Material::Dielectric(1.5);
+    let material1 = Arc::new(Dielectric::new(1.5));
     world.add(Sphere::stationary(
         Point3::new(0.0, 1.0, 0.0),
         1.0,
         material1,
     ));
 
-    let material2 = Material::Lambertian(Vector3::new(0.4, 0.2, 0.1));
+    let material2 = Arc::new(Lambertian::new(Vector3::new(0.4, 0.2, 0.1)));
     world.add(Sphere::stationary(
         Point3::new(-4.0, 1.0, 0.0),
         1.0,
         material2,
     ));
 
-    let material3 = Material::Metal(Vector3::new(0.7, 0.6, 0.5), 0.0);
+    let material3 = Arc::new(Metal::new(Vector3::new(0.7, 0.6, 0.5), 0.0));
     world.add(Sphere::stationary(
         Point3::new(4.0, 1.0, 0.0),
         1.0,
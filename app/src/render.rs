@@ -1,24 +1,30 @@
 use lib_ray_tracer::{
+    background::Background,
     bvh::BVHNode,
     camera::{self, Camera},
-    geometry::Sphere,
-    material::Material,
+    geometry::{BoxShape, Plane, Quad, Sphere},
+    material::{Dielectric, DiffuseLight, Lambertian, Metal, TexturedLambertian},
     texture::Texture,
+    transform::Transformed,
     world::World,
     Renderer,
 };
-use nalgebra::{Point3, Vector3};
+use nalgebra::{Matrix4, Point3, Vector3};
 use rand::{thread_rng, Rng};
 use image::{ImageBuffer, Rgba};
+use std::sync::Arc;
 
 pub fn render() {
-    let img = match 2 {
+    let img = match 5 {
         0 => random_spheres(),
         1 => two_spheres(),
-        //2 => earth(),
+        2 => earth(),
+        3 => simple_light(),
+        4 => quads(),
+        5 => cornell_box(),
         _ => panic!()
     };
-    img.save("generated_images/24_two_spheres.png").unwrap();
+    img.save("generated_images/28_cornell_box.png").unwrap();
 }
 
 fn random_spheres() -> ImageBuffer<Rgba<u8>, Vec<u8>> {
@@ -41,12 +47,14 @@ fn random_spheres() -> ImageBuffer<Rgba<u8>, Vec<u8>> {
     let mut rng = thread_rng();
 
     //let ground_mat = Material::Lambertian(Vector3::new(0.5, 0.5, 0.5));
-    static EVEN: Texture = Texture::SolidColor(Vector3::new(0.2, 0.3, 0.1));
-    static ODD: Texture = Texture::SolidColor(Vector3::new(0.9, 0.9, 0.9));
-    let checker = Material::TexturedLambertian(Texture::Checker(3.0, &EVEN, &ODD));
-    world.add(Sphere::stationary(
-        Point3::new(0.0, -1000.0, 0.0),
-        1000.0,
+    let checker = Arc::new(TexturedLambertian::new(Texture::checker(
+        3.0,
+        Texture::SolidColor(Vector3::new(0.2, 0.3, 0.1)),
+        Texture::SolidColor(Vector3::new(0.9, 0.9, 0.9)),
+    )));
+    world.add(Plane::new(
+        Point3::origin(),
+        Vector3::new(0.0, 1.0, 0.0),
         checker,
     ));
 
@@ -68,7 +76,7 @@ fn random_spheres() -> ImageBuffer<Rgba<u8>, Vec<u8>> {
                         rng.gen::<f64>() * rng.gen::<f64>(),
                         rng.gen::<f64>() * rng.gen::<f64>(),
                     );
-                    let sphere_material = Material::Lambertian(albedo);
+                    let sphere_material = Arc::new(Lambertian::new(albedo));
                     let center2 = center + Vector3::new(0.0, rng.gen_range(0.0..=0.5), 0.0);
                     world.add(Sphere::moving(center, center2, 0.2, sphere_material));
                 } else if choose_mat < 0.95 {
@@ -79,47 +87,44 @@ fn random_spheres() -> ImageBuffer<Rgba<u8>, Vec<u8>> {
                         rng.gen_range(0.5..=1.0),
                     );
                     let fuzz = rng.gen_range(0.0..=0.5);
-                    let sphere_material = Material::Metal(albedo, fuzz);
+                    let sphere_material = Arc::new(Metal::new(albedo, fuzz));
                     world.add(Sphere::stationary(center, 0.2, sphere_material));
                 } else {
                     // glass
-                    let sphere_material = Material::Dielectric(1.5);
+                    let sphere_material = Arc::new(Dielectric::new(1.5));
                     world.add(Sphere::stationary(center, 0.2, sphere_material));
                 }
             }
         }
     }
 
-    let material1 = Material::Dielectric(1.5);
+    let material1 = Arc::new(Dielectric::new(1.5));
     world.add(Sphere::stationary(
         Point3::new(0.0, 1.0, 0.0),
         1.0,
         material1,
     ));
 
-    let material2 = Material::Lambertian(Vector3::new(0.4, 0.2, 0.1));
+    let material2 = Arc::new(Lambertian::new(Vector3::new(0.4, 0.2, 0.1)));
     world.add(Sphere::stationary(
         Point3::new(-4.0, 1.0, 0.0),
         1.0,
         material2,
     ));
 
-    let material3 = Material::Metal(Vector3::new(0.7, 0.6, 0.5), 0.0);
+    let material3 = Arc::new(Metal::new(Vector3::new(0.7, 0.6, 0.5), 0.0));
     world.add(Sphere::stationary(
         Point3::new(4.0, 1.0, 0.0),
         1.0,
         material3,
     ));
 
-    // Create a new world made of only one object, a `BVHNode`
-    let mut world2 = World::empty();
-    let l = world.objects().len();
-    let mut objects = world.objects().drain(0..l).map(Some).collect();
-    world2.add(BVHNode::new(&mut objects, 0, l));
+    // The spheres are placed in a `BVHNode`, the ground plane is tested separately.
+    world.build_bvh();
 
     // Render the world that uses BVH
     let renderer = Renderer::new(aspect_ratio, image_width, camera);
-    renderer.render_parallel_image(&world2)
+    renderer.render_parallel_image(&world)
 }
 
 fn two_spheres() -> ImageBuffer<Rgba<u8>, Vec<u8>> {
@@ -140,13 +145,15 @@ fn two_spheres() -> ImageBuffer<Rgba<u8>, Vec<u8>> {
 
     let mut world = World::empty();
 
-    static EVEN: Texture = Texture::SolidColor(Vector3::new(0.2, 0.3, 0.1));
-    static ODD: Texture = Texture::SolidColor(Vector3::new(0.9, 0.9, 0.9));
-    let checker = Material::TexturedLambertian(Texture::Checker(3.0, &EVEN, &ODD));
+    let checker = Arc::new(TexturedLambertian::new(Texture::checker(
+        3.0,
+        Texture::SolidColor(Vector3::new(0.2, 0.3, 0.1)),
+        Texture::SolidColor(Vector3::new(0.9, 0.9, 0.9)),
+    )));
     world.add(Sphere::stationary(
         Point3::new(0.0, -10.0, 0.0),
         10.0,
-        checker,
+        checker.clone(),
     ));
 
     world.add(Sphere::stationary(
@@ -166,7 +173,7 @@ fn two_spheres() -> ImageBuffer<Rgba<u8>, Vec<u8>> {
     renderer.render_parallel_image(&world2)
 }
 
-/*fn earth() -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+fn earth() -> ImageBuffer<Rgba<u8>, Vec<u8>> {
     let camera = Camera::new(
         100,
         50,
@@ -180,13 +187,11 @@ fn two_spheres() -> ImageBuffer<Rgba<u8>, Vec<u8>> {
     );
 
     let mut world = World::empty();
-    let mut rng = thread_rng();
 
-    let earth_image: DynamicImage = image::open("./assets/earthmap.jpg").unwrap();
-    let earth_surface = Material::TexturedLambertian(Texture::Image(&earth_image));
+    let earth_texture = Texture::load_image("./assets/earthmap.jpg").unwrap();
+    let earth_surface = Arc::new(TexturedLambertian::new(earth_texture));
     world.add(Sphere::stationary(Point3::new(0.0, 0.0, 0.0), 2.0, earth_surface));
 
-
     // Create a new world made of only one object, a `BVHNode`
     let mut world2 = World::empty();
     let l = world.objects().len();
@@ -196,4 +201,201 @@ fn two_spheres() -> ImageBuffer<Rgba<u8>, Vec<u8>> {
     // Render the world that uses BVH
     let renderer = Renderer::new(16.0/9.0, 400, camera);
     renderer.render_parallel_image(&world2)
-}*/
\ No newline at end of file
+}
+fn simple_light() -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let camera = Camera::new(
+        100,
+        50,
+        20.0,
+        Point3::new(26.0, 3.0, 6.0),
+        Point3::new(0.0, 2.0, 0.0),
+        Vector3::new(0.0, 1.0, 0.0),
+        camera::Gamma::Gamma2,
+        0.0,
+        10.0,
+    );
+
+    let mut world = World::empty();
+
+    let checker = Arc::new(TexturedLambertian::new(Texture::checker(
+        1.0,
+        Texture::SolidColor(Vector3::new(0.2, 0.3, 0.1)),
+        Texture::SolidColor(Vector3::new(0.9, 0.9, 0.9)),
+    )));
+    world.add(Plane::new(
+        Point3::origin(),
+        Vector3::new(0.0, 1.0, 0.0),
+        checker,
+    ));
+    world.add(Sphere::stationary(
+        Point3::new(0.0, 2.0, 0.0),
+        2.0,
+        Arc::new(Lambertian::new(Vector3::new(0.4, 0.2, 0.1))),
+    ));
+
+    let light = Arc::new(DiffuseLight::new(Texture::SolidColor(Vector3::new(
+        4.0, 4.0, 4.0,
+    ))));
+    world.add(Sphere::stationary(Point3::new(0.0, 7.0, 0.0), 2.0, light));
+
+    // The spheres are placed in a `BVHNode`, the ground plane is tested separately.
+    world.build_bvh();
+    // The scene is only lit by the emissive sphere.
+    world.set_background(Background::Solid(Vector3::zeros()));
+
+    // Render the world that uses BVH
+    let renderer = Renderer::new(16.0 / 9.0, 400, camera);
+    renderer.render_parallel_image(&world)
+}
+
+fn quads() -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let camera = Camera::new(
+        100,
+        50,
+        80.0,
+        Point3::new(0.0, 0.0, 9.0),
+        Point3::new(0.0, 0.0, 0.0),
+        Vector3::new(0.0, 1.0, 0.0),
+        camera::Gamma::Gamma2,
+        0.0,
+        10.0,
+    );
+
+    let mut world = World::empty();
+
+    let left_red = Arc::new(Lambertian::new(Vector3::new(1.0, 0.2, 0.2)));
+    let back_green = Arc::new(Lambertian::new(Vector3::new(0.2, 1.0, 0.2)));
+    let right_blue = Arc::new(Lambertian::new(Vector3::new(0.2, 0.2, 1.0)));
+    let upper_orange = Arc::new(Lambertian::new(Vector3::new(1.0, 0.5, 0.0)));
+    let lower_teal = Arc::new(Lambertian::new(Vector3::new(0.2, 0.8, 0.8)));
+
+    world.add(Quad::new(
+        Point3::new(-3.0, -2.0, 5.0),
+        Vector3::new(0.0, 0.0, -4.0),
+        Vector3::new(0.0, 4.0, 0.0),
+        left_red,
+    ));
+    world.add(Quad::new(
+        Point3::new(-2.0, -2.0, 0.0),
+        Vector3::new(4.0, 0.0, 0.0),
+        Vector3::new(0.0, 4.0, 0.0),
+        back_green,
+    ));
+    world.add(Quad::new(
+        Point3::new(3.0, -2.0, 1.0),
+        Vector3::new(0.0, 0.0, 4.0),
+        Vector3::new(0.0, 4.0, 0.0),
+        right_blue,
+    ));
+    world.add(Quad::new(
+        Point3::new(-2.0, 3.0, 1.0),
+        Vector3::new(4.0, 0.0, 0.0),
+        Vector3::new(0.0, 0.0, 4.0),
+        upper_orange,
+    ));
+    world.add(Quad::new(
+        Point3::new(-2.0, -3.0, 5.0),
+        Vector3::new(4.0, 0.0, 0.0),
+        Vector3::new(0.0, 0.0, -4.0),
+        lower_teal,
+    ));
+
+    // The quads are placed in a `BVHNode`.
+    world.build_bvh();
+
+    // Render the world that uses BVH
+    let renderer = Renderer::new(1.0, 400, camera);
+    renderer.render_parallel_image(&world)
+}
+
+fn cornell_box() -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let camera = Camera::new(
+        200,
+        50,
+        40.0,
+        Point3::new(278.0, 278.0, -800.0),
+        Point3::new(278.0, 278.0, 0.0),
+        Vector3::new(0.0, 1.0, 0.0),
+        camera::Gamma::Gamma2,
+        0.0,
+        10.0,
+    );
+
+    let mut world = World::empty();
+
+    let red = Arc::new(Lambertian::new(Vector3::new(0.65, 0.05, 0.05)));
+    let white = Arc::new(Lambertian::new(Vector3::new(0.73, 0.73, 0.73)));
+    let green = Arc::new(Lambertian::new(Vector3::new(0.12, 0.45, 0.15)));
+    let light = Arc::new(DiffuseLight::new(Texture::SolidColor(Vector3::new(
+        15.0, 15.0, 15.0,
+    ))));
+
+    world.add(Quad::new(
+        Point3::new(555.0, 0.0, 0.0),
+        Vector3::new(0.0, 555.0, 0.0),
+        Vector3::new(0.0, 0.0, 555.0),
+        green,
+    ));
+    world.add(Quad::new(
+        Point3::new(0.0, 0.0, 0.0),
+        Vector3::new(0.0, 555.0, 0.0),
+        Vector3::new(0.0, 0.0, 555.0),
+        red,
+    ));
+    // The light faces the floor.
+    world.add(Quad::new(
+        Point3::new(343.0, 554.0, 332.0),
+        Vector3::new(-130.0, 0.0, 0.0),
+        Vector3::new(0.0, 0.0, -105.0),
+        light,
+    ));
+    world.add(Quad::new(
+        Point3::new(0.0, 0.0, 0.0),
+        Vector3::new(555.0, 0.0, 0.0),
+        Vector3::new(0.0, 0.0, 555.0),
+        white.clone(),
+    ));
+    world.add(Quad::new(
+        Point3::new(555.0, 555.0, 555.0),
+        Vector3::new(-555.0, 0.0, 0.0),
+        Vector3::new(0.0, 0.0, -555.0),
+        white.clone(),
+    ));
+    world.add(Quad::new(
+        Point3::new(0.0, 0.0, 555.0),
+        Vector3::new(555.0, 0.0, 0.0),
+        Vector3::new(0.0, 555.0, 0.0),
+        white.clone(),
+    ));
+
+    let tall_box = BoxShape::new(
+        Point3::new(0.0, 0.0, 0.0),
+        Point3::new(165.0, 330.0, 165.0),
+        white.clone(),
+    );
+    world.add(Transformed::new(
+        tall_box,
+        Matrix4::new_translation(&Vector3::new(265.0, 0.0, 295.0))
+            * Matrix4::from_axis_angle(&Vector3::y_axis(), 15f64.to_radians()),
+    ));
+
+    let short_box = BoxShape::new(
+        Point3::new(0.0, 0.0, 0.0),
+        Point3::new(165.0, 165.0, 165.0),
+        white,
+    );
+    world.add(Transformed::new(
+        short_box,
+        Matrix4::new_translation(&Vector3::new(130.0, 0.0, 65.0))
+            * Matrix4::from_axis_angle(&Vector3::y_axis(), -18f64.to_radians()),
+    ));
+
+    // The walls and the boxes are placed in a `BVHNode`.
+    world.build_bvh();
+    // The scene is only lit by the light of the ceiling.
+    world.set_background(Background::Solid(Vector3::zeros()));
+
+    // Render the world that uses BVH
+    let renderer = Renderer::new(1.0, 400, camera);
+    renderer.render_parallel_image(&world)
+}
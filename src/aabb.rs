@@ -53,6 +53,60 @@ impl AABB {
         }
     }
 
+    /// Creates the AABB of the space common to both boxes, which may be empty.
+    pub fn overlap(box0: &AABB, box1: &AABB) -> Self {
+        Self {
+            x: RealInterval {
+                min: box0.x.min.max(box1.x.min),
+                max: box0.x.max.min(box1.x.max),
+            },
+            y: RealInterval {
+                min: box0.y.min.max(box1.y.min),
+                max: box0.y.max.min(box1.y.max),
+            },
+            z: RealInterval {
+                min: box0.z.min.max(box1.z.min),
+                max: box0.z.max.min(box1.z.max),
+            },
+        }
+    }
+
+    /// Creates an AABB containing the whole space, used by unbounded hittables.
+    pub fn infinite() -> Self {
+        let interval = RealInterval {
+            min: f32::NEG_INFINITY,
+            max: f32::INFINITY,
+        };
+
+        Self {
+            x: interval,
+            y: interval,
+            z: interval,
+        }
+    }
+
+    /// Returns a copy of the box, where no side is narrower than a small delta.
+    /// Used by planar primitives, whose bounding box would otherwise have a null thickness.
+    pub fn pad(&self) -> Self {
+        let delta = 0.0001;
+        let pad_interval = |interval: &RealInterval| {
+            if interval.max - interval.min >= delta {
+                *interval
+            } else {
+                RealInterval {
+                    min: interval.min - delta / 2.0,
+                    max: interval.max + delta / 2.0,
+                }
+            }
+        };
+
+        Self {
+            x: pad_interval(&self.x),
+            y: pad_interval(&self.y),
+            z: pad_interval(&self.z),
+        }
+    }
+
     /// Converts an `axis` integer into the corresponding axis.
     pub fn axis(&self, axis: usize) -> &RealInterval {
         match axis {
@@ -0,0 +1,100 @@
+use image::ImageResult;
+use nalgebra::{Point3, Vector3};
+use rand::RngCore;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::environment::EnvironmentMap;
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::utility::spherical_uv;
+
+/// A function mapping the unit direction of a ray to the color of the background.
+pub type BackgroundFn = dyn Fn(&Vector3<f64>) -> Vector3<f64> + Send + Sync;
+
+/// The color of the light coming from the directions where the rays do not hit any object.
+#[derive(Clone)]
+pub enum Background {
+    /// The same color in every direction. Use black for scenes only lit by emissive materials.
+    Solid(Vector3<f64>),
+    /// A vertical linear gradient, from the `bottom` color to the `top` color.
+    Gradient {
+        bottom: Vector3<f64>,
+        top: Vector3<f64>,
+    },
+    /// A user-defined function, mapping the unit direction of the ray to a color.
+    Custom(Arc<BackgroundFn>),
+    /// A texture wrapped around the scene, using an equirectangular projection.
+    /// The `v` coordinate goes from the bottom (`-y`) to the top (`+y`) of the scene.
+    Equirectangular(Texture),
+    /// A high dynamic range environment map, importance sampled by the renderer.
+    Environment(Arc<EnvironmentMap>),
+}
+
+impl Background {
+    /// The white to blue gradient of a clear sky.
+    pub fn sky() -> Self {
+        Self::Gradient {
+            bottom: Vector3::new(1.0, 1.0, 1.0),
+            top: Vector3::new(0.5, 0.7, 1.0),
+        }
+    }
+
+    /// Creates a background from a function of the unit direction of the ray.
+    pub fn custom(f: impl Fn(&Vector3<f64>) -> Vector3<f64> + Send + Sync + 'static) -> Self {
+        Self::Custom(Arc::new(f))
+    }
+
+    /// Loads an equirectangular environment map from disk.
+    pub fn load_equirectangular(path: impl AsRef<Path>) -> ImageResult<Self> {
+        Ok(Self::Equirectangular(Texture::load_image(path)?))
+    }
+
+    /// Loads a high dynamic range environment map, such as an `.hdr` or `.exr` file, from disk.
+    pub fn load_environment(path: impl AsRef<Path>) -> ImageResult<Self> {
+        Ok(Self::Environment(Arc::new(EnvironmentMap::load(path)?)))
+    }
+
+    /// Computes the color of the background seen by the given ray.
+    pub fn color(&self, ray: &Ray) -> Vector3<f64> {
+        let unit_direction = ray.direction().normalize();
+
+        match self {
+            Self::Solid(color) => *color,
+            Self::Gradient { bottom, top } => {
+                let a = 0.5 * (unit_direction.y + 1.0);
+
+                (1.0 - a) * bottom + a * top
+            }
+            Self::Custom(f) => f(&unit_direction),
+            Self::Equirectangular(texture) => {
+                let (u, v) = spherical_uv(&unit_direction);
+                texture.value(u, v, Point3::from(unit_direction))
+            }
+            Self::Environment(map) => map.radiance(&unit_direction),
+        }
+    }
+
+    /// Samples a direction from which the background sends light, with its probability density.
+    /// Returns `None` if the background cannot be importance sampled.
+    pub fn sample(&self, rng: &mut dyn RngCore) -> Option<(Vector3<f64>, f64)> {
+        match self {
+            Self::Environment(map) => map.sample(rng),
+            _ => None,
+        }
+    }
+
+    /// The probability density of `sample` generating the given direction.
+    pub fn pdf(&self, direction: &Vector3<f64>) -> f64 {
+        match self {
+            Self::Environment(map) => map.pdf(direction),
+            _ => 0.0,
+        }
+    }
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Self::sky()
+    }
+}
@@ -1,4 +1,4 @@
-use rand::{thread_rng, Rng};
+use rand::{thread_rng, Rng, RngCore};
 use real_interval::RealInterval;
 use std::cmp::Ordering;
 
@@ -65,6 +65,13 @@ impl BVHNode {
         Self { left, right, bbox }
     }
 
+    /// Create a new tree containing every object of the given list.
+    pub fn from_objects(objects: Vec<Box<dyn Hittable + Sync>>) -> Self {
+        let len = objects.len();
+        let mut objects = objects.into_iter().map(Some).collect();
+        BVHNode::new(&mut objects, 0, len)
+    }
+
     /// Compare the coordinate of the boxes on the specified axis.
     fn box_compare(
         a: &Option<Box<dyn Hittable + Sync>>,
@@ -90,12 +97,18 @@ impl Hittable for BVHNode {
         &self.bbox
     }
 
-    fn hit(&self, ray: &Ray, t_interval: RealInterval, hit_record: &mut HitRecord) -> bool {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_interval: RealInterval,
+        hit_record: &mut HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> bool {
         if !self.bbox.hit(ray, t_interval) {
             return false;
         }
 
-        let hit_left = self.left.hit(ray, t_interval, hit_record);
+        let hit_left = self.left.hit(ray, t_interval, hit_record, rng);
         let hit_right = match &self.right {
             Some(right) => right.hit(
                 ray,
@@ -108,6 +121,7 @@ impl Hittable for BVHNode {
                     },
                 },
                 hit_record,
+                rng,
             ),
             None => false,
         };
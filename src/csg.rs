@@ -0,0 +1,304 @@
+use rand::RngCore;
+use real_interval::RealInterval;
+
+use crate::aabb::AABB;
+use crate::geometry::{Crossing, Hittable, Span};
+use crate::ray::Ray;
+use crate::world::HitRecord;
+
+/// The boolean operation used to combine the two solids of a `Csg`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// The space inside either solid.
+    Union,
+    /// The space inside both solids.
+    Intersection,
+    /// The space inside the first solid, but not inside the second one.
+    Difference,
+}
+
+impl Operation {
+    fn contains(&self, inside_left: bool, inside_right: bool) -> bool {
+        match self {
+            Operation::Union => inside_left || inside_right,
+            Operation::Intersection => inside_left && inside_right,
+            Operation::Difference => inside_left && !inside_right,
+        }
+    }
+}
+
+/// A solid built by combining two solids using constructive solid geometry.
+/// Both operands must enclose a volume, as reported by `Hittable::spans`: spheres, boxes, capped cylinders
+/// and cones, tori, planes (as half-spaces), and transformations or combinations of those.
+///
+/// The surface hit keeps the material and the texture coordinates of the operand it belongs to.
+pub struct Csg<A, B> {
+    left: A,
+    right: B,
+    operation: Operation,
+    bbox: AABB,
+}
+
+impl<A: Hittable, B: Hittable> Csg<A, B> {
+    /// Combines two solids. Panics if an operand does not enclose a volume, as reported by `Hittable::is_solid`.
+    pub fn new(left: A, right: B, operation: Operation) -> Self {
+        assert!(
+            left.is_solid() && right.is_solid(),
+            "the operands of a CSG must enclose a volume"
+        );
+
+        let bbox = match operation {
+            Operation::Union => AABB::from_boxes(left.bounding_box(), right.bounding_box()),
+            Operation::Intersection => AABB::overlap(left.bounding_box(), right.bounding_box()),
+            Operation::Difference => left.bounding_box().clone(),
+        };
+
+        Self {
+            left,
+            right,
+            operation,
+            bbox,
+        }
+    }
+
+    pub fn union(left: A, right: B) -> Self {
+        Self::new(left, right, Operation::Union)
+    }
+
+    pub fn intersection(left: A, right: B) -> Self {
+        Self::new(left, right, Operation::Intersection)
+    }
+
+    pub fn difference(left: A, right: B) -> Self {
+        Self::new(left, right, Operation::Difference)
+    }
+
+    /// The spans of the ray inside the combined solid.
+    fn combined_spans(&self, ray: &Ray) -> Vec<Span> {
+        // The operands are solid, so they always return their spans.
+        let operand_spans = |operand: &dyn Hittable| operand.spans(ray).unwrap_or_default();
+
+        // Each crossing of an operand toggles the ray between its inside and its outside.
+        struct Event {
+            crossing: Crossing,
+            left: bool,
+            entering: bool,
+        }
+
+        let mut events = vec![];
+        for (spans, left) in [
+            (operand_spans(&self.left), true),
+            (operand_spans(&self.right), false),
+        ] {
+            for span in spans {
+                events.push(Event {
+                    crossing: span.enter,
+                    left,
+                    entering: true,
+                });
+                events.push(Event {
+                    crossing: span.exit,
+                    left,
+                    entering: false,
+                });
+            }
+        }
+        events.sort_by(|a, b| a.crossing.t.total_cmp(&b.crossing.t));
+
+        let mut spans = vec![];
+        let mut enter: Option<Crossing> = None;
+        let (mut inside_left, mut inside_right) = (false, false);
+
+        for event in events {
+            match event.left {
+                true => inside_left = event.entering,
+                false => inside_right = event.entering,
+            }
+
+            let inside = self.operation.contains(inside_left, inside_right);
+            if inside == enter.is_some() {
+                continue;
+            }
+
+            // Entering the result while leaving an operand, or the opposite, happens on the surface
+            // of a subtracted solid: its normal must be flipped to point outside of the result.
+            let mut crossing = event.crossing;
+            if event.entering != inside {
+                crossing.outward_normal = -crossing.outward_normal;
+            }
+
+            match enter.take() {
+                Some(enter) => spans.push(Span {
+                    enter,
+                    exit: crossing,
+                }),
+                None => enter = Some(crossing),
+            }
+        }
+
+        spans
+    }
+}
+
+impl<A: Hittable, B: Hittable> Hittable for Csg<A, B> {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_interval: RealInterval,
+        hit_record: &mut HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> bool {
+        if !self.bbox.hit(ray, t_interval) {
+            return false;
+        }
+
+        let closest = self
+            .combined_spans(ray)
+            .into_iter()
+            .flat_map(|span| [span.enter, span.exit])
+            .find(|crossing| {
+                (t_interval.min as f64) < crossing.t && crossing.t < t_interval.max as f64
+            });
+
+        match closest {
+            Some(crossing) => {
+                crossing.record(ray, hit_record);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        &self.bbox
+    }
+
+    fn is_bounded(&self) -> bool {
+        match self.operation {
+            Operation::Union => self.left.is_bounded() && self.right.is_bounded(),
+            Operation::Intersection => self.left.is_bounded() || self.right.is_bounded(),
+            Operation::Difference => self.left.is_bounded(),
+        }
+    }
+
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        Some(self.combined_spans(ray))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Sphere;
+    use crate::material::Lambertian;
+    use nalgebra::{Point3, Vector3};
+    use std::sync::Arc;
+
+    /// Two unit spheres centered at `x = -0.5` and `x = 0.5`.
+    fn operands() -> (Sphere, Sphere) {
+        let material = Arc::new(Lambertian::default());
+        (
+            Sphere::stationary(Point3::new(-0.5, 0.0, 0.0), 1.0, material.clone()),
+            Sphere::stationary(Point3::new(0.5, 0.0, 0.0), 1.0, material),
+        )
+    }
+
+    /// A ray along the `x` axis, entering the left sphere at `t = 3.5` and the right one at `t = 4.5`.
+    fn ray() -> Ray {
+        Ray::new(
+            Point3::new(-5.0, 0.0, 0.0),
+            Vector3::new(1.0, 0.0, 0.0),
+            0.0,
+        )
+    }
+
+    fn intervals(csg: &impl Hittable, ray: &Ray) -> Vec<(f64, f64)> {
+        csg.spans(ray)
+            .unwrap()
+            .iter()
+            .map(|span| (span.enter.t, span.exit.t))
+            .collect()
+    }
+
+    fn assert_intervals(csg: &impl Hittable, expected: &[(f64, f64)]) {
+        let intervals = intervals(csg, &ray());
+        assert_eq!(intervals.len(), expected.len(), "{intervals:?}");
+        for (interval, expected) in intervals.iter().zip(expected) {
+            assert!((interval.0 - expected.0).abs() < 1e-9, "{intervals:?}");
+            assert!((interval.1 - expected.1).abs() < 1e-9, "{intervals:?}");
+        }
+    }
+
+    fn closest_hit(csg: &impl Hittable, ray: &Ray) -> Option<HitRecord> {
+        let mut hit_record = HitRecord::default();
+        csg.hit(
+            ray,
+            RealInterval::min_max(0.001, f32::INFINITY),
+            &mut hit_record,
+            &mut rand::thread_rng(),
+        )
+        .then_some(hit_record)
+    }
+
+    #[test]
+    fn union() {
+        let (left, right) = operands();
+        let csg = Csg::union(left, right);
+
+        assert_intervals(&csg, &[(3.5, 6.5)]);
+        assert!((closest_hit(&csg, &ray()).unwrap().t - 3.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn intersection() {
+        let (left, right) = operands();
+        let csg = Csg::intersection(left, right);
+
+        assert_intervals(&csg, &[(4.5, 5.5)]);
+        assert!((closest_hit(&csg, &ray()).unwrap().t - 4.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn difference() {
+        let (left, right) = operands();
+        let csg = Csg::difference(left, right);
+
+        assert_intervals(&csg, &[(3.5, 4.5)]);
+        let hit_record = closest_hit(&csg, &ray()).unwrap();
+        assert!((hit_record.t - 3.5).abs() < 1e-9);
+        assert!((hit_record.normal - Vector3::new(-1.0, 0.0, 0.0)).norm() < 1e-9);
+
+        // The wall of the cavity left by the right sphere faces the cavity.
+        let spans = csg.spans(&ray()).unwrap();
+        assert!((spans[0].exit.outward_normal - Vector3::new(1.0, 0.0, 0.0)).norm() < 1e-9);
+
+        let from_cavity = Ray::new(
+            Point3::new(0.2, 0.0, 0.0),
+            Vector3::new(-1.0, 0.0, 0.0),
+            0.0,
+        );
+        let hit_record = closest_hit(&csg, &from_cavity).unwrap();
+        assert!((hit_record.t - 0.7).abs() < 1e-9);
+        assert!(hit_record.front_face);
+        assert!((hit_record.normal - Vector3::new(1.0, 0.0, 0.0)).norm() < 1e-9);
+    }
+
+    #[test]
+    fn difference_misses_the_subtracted_solid_alone() {
+        let (left, right) = operands();
+        let csg = Csg::difference(left, right);
+
+        // Crosses the right sphere only.
+        let ray = Ray::new(
+            Point3::new(1.2, -5.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            0.0,
+        );
+        assert!(intervals(&csg, &ray).is_empty());
+        assert!(closest_hit(&csg, &ray).is_none());
+    }
+}
@@ -0,0 +1,290 @@
+use image::{DynamicImage, ImageResult};
+use nalgebra::Vector3;
+use rand::{Rng, RngCore};
+use std::f64::consts::PI;
+use std::path::Path;
+
+use crate::utility::spherical_uv;
+
+/// A high dynamic range environment map, wrapped around the scene using an equirectangular projection.
+/// The map can be importance sampled: bright regions, such as the sun, are directly sampled by the renderer.
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    /// The linear radiance of each pixel, row by row, starting from the top of the image.
+    pixels: Vec<Vector3<f64>>,
+    /// The distribution used to sample the pixels proportionally to their contribution.
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    /// Loads an environment map from disk. `.hdr` and `.exr` files keep their full dynamic range.
+    pub fn load(path: impl AsRef<Path>) -> ImageResult<Self> {
+        Ok(Self::from_image(&image::open(path)?))
+    }
+
+    /// Creates an environment map from an image already in memory.
+    pub fn from_image(image: &DynamicImage) -> Self {
+        let image = image.to_rgb32f();
+        let width = image.width() as usize;
+        let height = image.height() as usize;
+        assert!(width > 0 && height > 0);
+
+        let pixels: Vec<Vector3<f64>> = image
+            .pixels()
+            .map(|p| Vector3::new(p[0] as f64, p[1] as f64, p[2] as f64))
+            .collect();
+
+        // Each pixel is weighted by its luminance, and by the solid angle it covers on the sphere.
+        let weights: Vec<Vec<f64>> = (0..height)
+            .map(|y| {
+                let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+                (0..width)
+                    .map(|x| luminance(&pixels[y * width + x]) * sin_theta)
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            width,
+            height,
+            pixels,
+            distribution: Distribution2D::new(weights),
+        }
+    }
+
+    /// The radiance coming from the given direction.
+    pub fn radiance(&self, direction: &Vector3<f64>) -> Vector3<f64> {
+        let (x, y) = self.image_coordinates(direction);
+        self.pixels[self.pixel_index(x, y)]
+    }
+
+    /// Samples a unit direction proportionally to the radiance of the map.
+    /// Returns the direction and its probability density, with respect to solid angle.
+    pub fn sample(&self, rng: &mut dyn RngCore) -> Option<(Vector3<f64>, f64)> {
+        let (x, y, map_pdf) = self.distribution.sample(rng.gen(), rng.gen());
+        if map_pdf == 0.0 {
+            return None;
+        }
+
+        let theta = PI * (1.0 - y);
+        let phi = 2.0 * PI * x;
+        let sin_theta = theta.sin();
+        if sin_theta == 0.0 {
+            return None;
+        }
+
+        // Inverse of `spherical_uv`.
+        let direction = Vector3::new(-phi.cos() * sin_theta, -theta.cos(), phi.sin() * sin_theta);
+
+        // Change of variables from the image to the sphere of directions.
+        Some((direction, map_pdf / (2.0 * PI * PI * sin_theta)))
+    }
+
+    /// The probability density of `sample` generating the given direction.
+    pub fn pdf(&self, direction: &Vector3<f64>) -> f64 {
+        let (x, y) = self.image_coordinates(direction);
+        let sin_theta = (PI * y).sin();
+        if sin_theta == 0.0 {
+            return 0.0;
+        }
+
+        self.distribution.pdf(x, y) / (2.0 * PI * PI * sin_theta)
+    }
+
+    /// Converts a direction to continuous image coordinates in `[0, 1]`, starting from the top-left corner.
+    fn image_coordinates(&self, direction: &Vector3<f64>) -> (f64, f64) {
+        let (u, v) = spherical_uv(&direction.normalize());
+        (u, 1.0 - v)
+    }
+
+    fn pixel_index(&self, x: f64, y: f64) -> usize {
+        let x = ((x * self.width as f64) as usize).min(self.width - 1);
+        let y = ((y * self.height as f64) as usize).min(self.height - 1);
+        y * self.width + x
+    }
+}
+
+/// The luminance of a linear RGB color.
+fn luminance(color: &Vector3<f64>) -> f64 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+/// A piecewise-constant 1D distribution on `[0, 1]`.
+struct Distribution1D {
+    function: Vec<f64>,
+    /// The cumulative distribution function, with one more value than `function`.
+    cdf: Vec<f64>,
+    /// The integral of `function` on `[0, 1]`.
+    integral: f64,
+}
+
+impl Distribution1D {
+    fn new(function: Vec<f64>) -> Self {
+        let n = function.len();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 1..=n {
+            cdf[i] = cdf[i - 1] + function[i - 1].max(0.0) / n as f64;
+        }
+
+        let integral = cdf[n];
+        for (i, value) in cdf.iter_mut().enumerate().skip(1) {
+            // A null function is sampled uniformly.
+            *value = match integral == 0.0 {
+                true => i as f64 / n as f64,
+                false => *value / integral,
+            };
+        }
+
+        Self {
+            function,
+            cdf,
+            integral,
+        }
+    }
+
+    /// Samples the distribution using a uniform number `u` in `[0, 1)`.
+    /// Returns the sampled value, its probability density, and the index of its segment.
+    fn sample(&self, u: f64) -> (f64, f64, usize) {
+        let n = self.function.len();
+        let offset = (self.cdf.partition_point(|&c| c <= u) - 1).min(n - 1);
+
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        let du = match width > 0.0 {
+            true => (u - self.cdf[offset]) / width,
+            false => 0.0,
+        };
+
+        let pdf = match self.integral > 0.0 {
+            true => self.function[offset] / self.integral,
+            false => 1.0,
+        };
+
+        ((offset as f64 + du) / n as f64, pdf, offset)
+    }
+
+    /// The probability density of the distribution at `x`.
+    fn pdf(&self, x: f64) -> f64 {
+        let n = self.function.len();
+        let offset = ((x * n as f64) as usize).min(n - 1);
+
+        match self.integral > 0.0 {
+            true => self.function[offset] / self.integral,
+            false => 1.0,
+        }
+    }
+}
+
+/// A piecewise-constant 2D distribution on `[0, 1]²`, sampled using a marginal distribution over the rows
+/// and a conditional distribution over the columns of each row.
+struct Distribution2D {
+    conditionals: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// Creates the distribution from a function given row by row.
+    fn new(rows: Vec<Vec<f64>>) -> Self {
+        let conditionals: Vec<Distribution1D> = rows.into_iter().map(Distribution1D::new).collect();
+        let marginal = Distribution1D::new(conditionals.iter().map(|c| c.integral).collect());
+
+        Self {
+            conditionals,
+            marginal,
+        }
+    }
+
+    /// Samples a point `(x, y)` using two uniform numbers in `[0, 1)`, and returns it with its probability density.
+    fn sample(&self, u1: f64, u2: f64) -> (f64, f64, f64) {
+        let (y, pdf_y, row) = self.marginal.sample(u2);
+        let (x, pdf_x, _) = self.conditionals[row].sample(u1);
+
+        (x, y, pdf_x * pdf_y)
+    }
+
+    /// The probability density of the distribution at `(x, y)`.
+    fn pdf(&self, x: f64, y: f64) -> f64 {
+        let n = self.conditionals.len();
+        let row = ((y * n as f64) as usize).min(n - 1);
+
+        self.marginal.pdf(y) * self.conditionals[row].pdf(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, Rgb32FImage};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// An 8x4 map of radiance 1, with a single pixel of radiance 50.
+    const BRIGHT_PIXEL: (u32, u32) = (2, 1);
+    fn map() -> EnvironmentMap {
+        let image = Rgb32FImage::from_fn(8, 4, |x, y| match (x, y) == BRIGHT_PIXEL {
+            true => Rgb([50.0, 50.0, 50.0]),
+            false => Rgb([1.0, 1.0, 1.0]),
+        });
+        EnvironmentMap::from_image(&DynamicImage::ImageRgb32F(image))
+    }
+
+    #[test]
+    fn sampled_pdf_matches_pdf() {
+        let map = map();
+        let mut rng = StdRng::seed_from_u64(1);
+
+        for _ in 0..10_000 {
+            let (direction, pdf) = map.sample(&mut rng).unwrap();
+            assert!((direction.norm() - 1.0).abs() < 1e-9);
+            assert!(
+                (map.pdf(&direction) - pdf).abs() < 1e-6 * pdf,
+                "{pdf} != {}",
+                map.pdf(&direction)
+            );
+        }
+    }
+
+    #[test]
+    fn pdf_is_normalized() {
+        let map = map();
+        const STEPS: usize = 512;
+        let (theta_step, phi_step) = (PI / STEPS as f64, 2.0 * PI / STEPS as f64);
+
+        let mut integral = 0.0;
+        for i in 0..STEPS {
+            let theta = (i as f64 + 0.5) * theta_step;
+            for j in 0..STEPS {
+                let phi = (j as f64 + 0.5) * phi_step;
+                let direction = Vector3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                integral += map.pdf(&direction) * theta.sin() * theta_step * phi_step;
+            }
+        }
+        assert!((integral - 1.0).abs() < 1e-3, "{integral}");
+    }
+
+    #[test]
+    fn bright_pixel_is_sampled_proportionally() {
+        let map = map();
+        let mut rng = StdRng::seed_from_u64(2);
+
+        // The pixels are weighted by their radiance and by the sine of the latitude of their row.
+        let sin_theta = |y: u32| (PI * (y as f64 + 0.5) / 4.0).sin();
+        let total =
+            (0..4).map(|y| 8.0 * sin_theta(y)).sum::<f64>() + 49.0 * sin_theta(BRIGHT_PIXEL.1);
+        let expected = 50.0 * sin_theta(BRIGHT_PIXEL.1) / total;
+
+        const SAMPLES: usize = 20_000;
+        let bright = (0..SAMPLES)
+            .filter(|_| map.radiance(&map.sample(&mut rng).unwrap().0).x == 50.0)
+            .count();
+        let fraction = bright as f64 / SAMPLES as f64;
+        assert!(
+            (fraction - expected).abs() < 0.01,
+            "{fraction} != {expected}"
+        );
+    }
+}
@@ -1,30 +1,148 @@
 use nalgebra::{Point3, Vector3};
+use rand::RngCore;
 use real_interval::RealInterval;
+use std::sync::Arc;
 
 use crate::aabb::AABB;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::utility::{orthonormal_basis, solve_quadratic, solve_quartic, spherical_uv};
 use crate::world::HitRecord;
 
-/// An object hittable by a ray.
-pub trait Hittable {
+/// An object hittable by a ray. Hittables are shared between the rendering threads.
+pub trait Hittable: Send + Sync {
     /// Check if the given ray hits the hittable. If so, it adds informations about the hit to `hit_record`.
-    fn hit(&self, ray: &Ray, t_interval: RealInterval, hit_record: &mut HitRecord) -> bool;
+    /// `rng` is used by the hittables sampling their hits, such as participating media.
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_interval: RealInterval,
+        hit_record: &mut HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> bool;
     fn bounding_box(&self) -> &AABB;
+
+    /// If the hittable fits in its bounding box. Unbounded hittables, such as `Plane`,
+    /// are kept outside of the Bounding Volume Hierarchy by `World`.
+    fn is_bounded(&self) -> bool {
+        true
+    }
+
+    /// If the hittable encloses a volume, whose boundary crossings are given by `spans`.
+    /// Only solid hittables can be combined by `Csg`.
+    fn is_solid(&self) -> bool {
+        false
+    }
+
+    /// Returns every segment of the ray lying inside the hittable, sorted along the ray and for any `t`,
+    /// or `None` if the hittable does not enclose a volume. Must return `Some` for solid hittables.
+    fn spans(&self, _ray: &Ray) -> Option<Vec<Span>> {
+        None
+    }
+}
+
+/// Allows sharing a hittable, for instance to place it several times using `Transformed`.
+impl<T: Hittable + ?Sized> Hittable for Arc<T> {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_interval: RealInterval,
+        hit_record: &mut HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> bool {
+        (**self).hit(ray, t_interval, hit_record, rng)
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        (**self).bounding_box()
+    }
+
+    fn is_bounded(&self) -> bool {
+        (**self).is_bounded()
+    }
+
+    fn is_solid(&self) -> bool {
+        (**self).is_solid()
+    }
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        (**self).spans(ray)
+    }
+}
+
+/// A point where a ray crosses the surface of a solid hittable.
+#[derive(Clone)]
+pub struct Crossing {
+    /// The `t` value of the ray at the crossing, which may be negative or infinite.
+    pub t: f64,
+    /// The unit normal pointing outside of the solid.
+    pub outward_normal: Vector3<f64>,
+    pub u: f64,
+    pub v: f64,
+    /// The derivatives of the position with respect to `u` and `v`.
+    pub dpdu: Vector3<f64>,
+    pub dpdv: Vector3<f64>,
+    pub material: Arc<dyn Material>,
+}
+
+impl Crossing {
+    /// Fills the hit record with the crossing. `t` must be positive.
+    pub(crate) fn record(&self, ray: &Ray, hit_record: &mut HitRecord) {
+        hit_record.t = self.t;
+        hit_record.hit_point = ray.at(self.t);
+        hit_record.u = self.u;
+        hit_record.v = self.v;
+        hit_record.dpdu = self.dpdu;
+        hit_record.dpdv = self.dpdv;
+        hit_record.material = self.material.clone();
+        hit_record.vertex_color = None;
+        hit_record.set_face_normal(ray, &self.outward_normal);
+    }
+}
+
+/// A segment of a ray lying inside a solid hittable.
+#[derive(Clone)]
+pub struct Span {
+    /// Where the ray enters the solid.
+    pub enter: Crossing,
+    /// Where the ray leaves the solid.
+    pub exit: Crossing,
+}
+
+/// The relative distance under which two crossings of a ray are considered as the same point.
+const CROSSING_EPSILON: f64 = 1e-9;
+
+/// Pairs the crossings of a ray with the surface of a closed primitive into the spans inside it.
+/// Coincident crossings, found when the ray passes through an edge or an apex, count as a single one.
+/// An odd number of crossings left, which happens when the ray grazes the surface, is considered as a miss.
+fn pair_crossings(mut crossings: Vec<Crossing>) -> Vec<Span> {
+    crossings.sort_by(|a, b| a.t.total_cmp(&b.t));
+    crossings.dedup_by(|b, a| (b.t - a.t).abs() <= CROSSING_EPSILON * a.t.abs().max(1.0));
+    if !crossings.len().is_multiple_of(2) {
+        return vec![];
+    }
+
+    crossings
+        .chunks_exact(2)
+        .map(|pair| Span {
+            enter: pair[0].clone(),
+            exit: pair[1].clone(),
+        })
+        .collect()
 }
 
 /// A basic Sphere geometry.
 pub struct Sphere {
     center1: Point3<f64>,
     radius: f64,
-    material: Material,
+    material: Arc<dyn Material>,
     is_moving: bool,
     center_vec: Vector3<f64>,
     bbox: AABB,
 }
 
 impl Sphere {
-    pub fn stationary(center: Point3<f64>, radius: f64, material: Material) -> Self {
+    pub fn stationary(center: Point3<f64>, radius: f64, material: Arc<dyn Material>) -> Self {
         let radius_vector = Vector3::new(radius, radius, radius);
         Self {
             center1: center,
@@ -40,7 +158,7 @@ impl Sphere {
         center1: Point3<f64>,
         center2: Point3<f64>,
         radius: f64,
-        material: Material,
+        material: Arc<dyn Material>,
     ) -> Self {
         let radius_vector = Vector3::new(radius, radius, radius);
         let bbox1 = AABB::from_points(center1 - radius_vector, center1 + radius_vector);
@@ -65,16 +183,63 @@ impl Sphere {
     }
 
     fn get_uv_coordinates(&self, point: Point3<f64>, u: &mut f64, v: &mut f64) {
-        let theta = f64::acos(-point.y);
-        let phi = f64::atan2(-point.z, point.x) + std::f64::consts::PI;
+        (*u, *v) = spherical_uv(&point.coords);
+    }
+
+    /// The derivatives of the point of the sphere with the given outward normal, with respect to `u` and `v`.
+    fn derivatives(&self, normal: &Vector3<f64>) -> (Vector3<f64>, Vector3<f64>) {
+        let sin_theta = (normal.x * normal.x + normal.z * normal.z)
+            .sqrt()
+            .max(1e-12);
+        let dpdu = self.radius * azimuth_derivative(normal.x, normal.z);
+        let dpdv = std::f64::consts::PI
+            * self.radius
+            * Vector3::new(
+                -normal.x * normal.y / sin_theta,
+                sin_theta,
+                -normal.z * normal.y / sin_theta,
+            );
 
-        *u = phi / (2.0 * std::f64::consts::PI);
-        *v = theta / std::f64::consts::PI;
+        (dpdu, dpdv)
+    }
+
+    /// Every intersection of the ray with the sphere, for any `t`.
+    fn intersections(&self, ray: &Ray) -> Vec<SurfaceHit> {
+        let center = self.center(ray.time());
+        let origin_to_center = ray.origin() - center;
+        let a = ray.direction().norm_squared();
+        let b = 2.0 * origin_to_center.dot(ray.direction());
+        let c = origin_to_center.norm_squared() - self.radius * self.radius;
+
+        solve_quadratic(a, b, c)
+            .into_iter()
+            .map(|t| {
+                let outward_normal = (origin_to_center + t * ray.direction()) / self.radius;
+                let (mut u, mut v) = (0.0, 0.0);
+                self.get_uv_coordinates(Point3::from(outward_normal), &mut u, &mut v);
+                let (dpdu, dpdv) = self.derivatives(&outward_normal);
+
+                SurfaceHit {
+                    t,
+                    outward_normal,
+                    u,
+                    v,
+                    dpdu,
+                    dpdv,
+                }
+            })
+            .collect()
     }
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, t_interval: RealInterval, hit_record: &mut HitRecord) -> bool {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_interval: RealInterval,
+        hit_record: &mut HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> bool {
         let origin_to_center = ray.origin() - self.center(ray.time());
         let a = ray.direction().norm_squared();
         let half_b = origin_to_center.dot(ray.direction());
@@ -103,11 +268,18 @@ impl Hittable for Sphere {
         // Modify the hit record accordingly
         hit_record.t = root;
         hit_record.hit_point = ray.at(hit_record.t);
-        self.get_uv_coordinates(hit_record.hit_point, &mut hit_record.u, &mut hit_record.v);
-        hit_record.material = self.material;
+        hit_record.material = self.material.clone();
+        hit_record.vertex_color = None;
 
         let outward_normal = (hit_record.hit_point - self.center(ray.time())) / self.radius;
         hit_record.set_face_normal(ray, &outward_normal);
+        // The coordinates are computed on the unit sphere, using the outward normal.
+        self.get_uv_coordinates(
+            Point3::from(outward_normal),
+            &mut hit_record.u,
+            &mut hit_record.v,
+        );
+        (hit_record.dpdu, hit_record.dpdv) = self.derivatives(&outward_normal);
 
         true // there's a hit
     }
@@ -115,4 +287,1101 @@ impl Hittable for Sphere {
     fn bounding_box(&self) -> &AABB {
         &self.bbox
     }
+
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        Some(solid_spans(self.intersections(ray), &self.material))
+    }
+}
+
+/// A planar quadrilateral, more precisely a parallelogram, defined by a corner `q` and two edges `u` and `v`.
+pub struct Quad {
+    q: Point3<f64>,
+    u: Vector3<f64>,
+    v: Vector3<f64>,
+    material: Arc<dyn Material>,
+    /// The unit normal of the plane containing the quad.
+    normal: Vector3<f64>,
+    /// The constant of the plane equation `normal . p = d`.
+    d: f64,
+    /// A vector used to compute the planar coordinates of a point.
+    w: Vector3<f64>,
+    bbox: AABB,
+}
+
+impl Quad {
+    pub fn new(
+        q: Point3<f64>,
+        u: Vector3<f64>,
+        v: Vector3<f64>,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        let n = u.cross(&v);
+        assert_ne!(n, Vector3::zeros(), "the edges of the quad are colinear");
+        let normal = n.normalize();
+
+        let bbox_diagonal1 = AABB::from_points(q, q + u + v);
+        let bbox_diagonal2 = AABB::from_points(q + u, q + v);
+
+        Self {
+            q,
+            u,
+            v,
+            material,
+            normal,
+            d: normal.dot(&q.coords),
+            w: n / n.norm_squared(),
+            bbox: AABB::from_boxes(&bbox_diagonal1, &bbox_diagonal2).pad(),
+        }
+    }
+}
+
+impl Quad {
+    /// The intersection of the ray with the quad, for any `t`.
+    fn intersection(&self, ray: &Ray) -> Option<SurfaceHit> {
+        let denominator = self.normal.dot(ray.direction());
+
+        // The ray is parallel to the plane: there's no hit
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.d - self.normal.dot(&ray.origin().coords)) / denominator;
+
+        // Checks if the hit point lies within the quad, using its planar coordinates
+        let planar_hit_vector = ray.origin() + t * ray.direction() - self.q;
+        let alpha = self.w.dot(&planar_hit_vector.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar_hit_vector));
+
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        Some(SurfaceHit {
+            t,
+            outward_normal: self.normal,
+            u: alpha,
+            v: beta,
+            dpdu: self.u,
+            dpdv: self.v,
+        })
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_interval: RealInterval,
+        hit_record: &mut HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> bool {
+        let Some(hit) = self.intersection(ray) else {
+            return false;
+        };
+        if hit.t <= t_interval.min as f64 || t_interval.max as f64 <= hit.t {
+            return false;
+        }
+
+        hit.record(ray, &self.material, hit_record);
+        true
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        &self.bbox
+    }
+}
+
+/// A single triangle, defined by its three vertices.
+/// The `u` and `v` coordinates of a hit are its barycentric coordinates relative to `p1` and `p2`.
+pub struct Triangle {
+    p0: Point3<f64>,
+    p1: Point3<f64>,
+    p2: Point3<f64>,
+    material: Arc<dyn Material>,
+    bbox: AABB,
+}
+
+impl Triangle {
+    pub fn new(
+        p0: Point3<f64>,
+        p1: Point3<f64>,
+        p2: Point3<f64>,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        Self {
+            p0,
+            p1,
+            p2,
+            material,
+            bbox: triangle_bounding_box(&p0, &p1, &p2),
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_interval: RealInterval,
+        hit_record: &mut HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> bool {
+        let Some((t, b1, b2)) = intersect_triangle(ray, t_interval, &self.p0, &self.p1, &self.p2)
+        else {
+            return false;
+        };
+
+        hit_record.t = t;
+        hit_record.hit_point = ray.at(t);
+        hit_record.u = b1;
+        hit_record.v = b2;
+        hit_record.dpdu = self.p1 - self.p0;
+        hit_record.dpdv = self.p2 - self.p0;
+        hit_record.material = self.material.clone();
+        hit_record.vertex_color = None;
+
+        let outward_normal = (self.p1 - self.p0).cross(&(self.p2 - self.p0)).normalize();
+        hit_record.set_face_normal(ray, &outward_normal);
+
+        true
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        &self.bbox
+    }
+}
+
+/// Computes the intersection of a ray and a triangle, using the Möller–Trumbore algorithm.
+/// Returns the `t` value of the hit, and the barycentric coordinates of the hit point relative to `p1` and `p2`.
+pub(crate) fn intersect_triangle(
+    ray: &Ray,
+    t_interval: RealInterval,
+    p0: &Point3<f64>,
+    p1: &Point3<f64>,
+    p2: &Point3<f64>,
+) -> Option<(f64, f64, f64)> {
+    let edge1 = p1 - p0;
+    let edge2 = p2 - p0;
+
+    let p_vec = ray.direction().cross(&edge2);
+    let determinant = edge1.dot(&p_vec);
+
+    // The ray is parallel to the triangle: there's no hit
+    if determinant.abs() < 1e-12 {
+        return None;
+    }
+    let inv_determinant = 1.0 / determinant;
+
+    let t_vec = ray.origin() - p0;
+    let b1 = t_vec.dot(&p_vec) * inv_determinant;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+
+    let q_vec = t_vec.cross(&edge1);
+    let b2 = ray.direction().dot(&q_vec) * inv_determinant;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(&q_vec) * inv_determinant;
+    if t <= t_interval.min as f64 || t_interval.max as f64 <= t {
+        return None;
+    }
+
+    Some((t, b1, b2))
+}
+
+/// The padded bounding box of a triangle, which may be parallel to an axis.
+pub(crate) fn triangle_bounding_box(p0: &Point3<f64>, p1: &Point3<f64>, p2: &Point3<f64>) -> AABB {
+    AABB::from_boxes(&AABB::from_points(*p0, *p1), &AABB::from_points(*p1, *p2)).pad()
+}
+
+/// An axis-aligned box, made of six quads, given two opposite corners. Use `Transformed` to rotate it.
+pub struct BoxShape {
+    /// The faces of the box, in the order front (`+z`), right (`+x`), back (`-z`), left (`-x`), top (`+y`)
+    /// and bottom (`-y`). Their normals point outwards.
+    faces: [Quad; 6],
+    bbox: AABB,
+}
+
+impl BoxShape {
+    pub fn new(a: Point3<f64>, b: Point3<f64>, material: Arc<dyn Material>) -> Self {
+        Self::with_face_materials(a, b, std::array::from_fn(|_| material.clone()))
+    }
+
+    /// Creates a box with a different material on each face, in the order front (`+z`), right (`+x`),
+    /// back (`-z`), left (`-x`), top (`+y`) and bottom (`-y`).
+    pub fn with_face_materials(
+        a: Point3<f64>,
+        b: Point3<f64>,
+        materials: [Arc<dyn Material>; 6],
+    ) -> Self {
+        let min = Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+        let max = Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+
+        let dx = Vector3::new(max.x - min.x, 0.0, 0.0);
+        let dy = Vector3::new(0.0, max.y - min.y, 0.0);
+        let dz = Vector3::new(0.0, 0.0, max.z - min.z);
+
+        let [front, right, back, left, top, bottom] = materials;
+        let faces = [
+            Quad::new(Point3::new(min.x, min.y, max.z), dx, dy, front),
+            Quad::new(Point3::new(max.x, min.y, max.z), -dz, dy, right),
+            Quad::new(Point3::new(max.x, min.y, min.z), -dx, dy, back),
+            Quad::new(Point3::new(min.x, min.y, min.z), dz, dy, left),
+            Quad::new(Point3::new(min.x, max.y, max.z), dx, -dz, top),
+            Quad::new(Point3::new(min.x, min.y, min.z), dx, dz, bottom),
+        ];
+
+        Self {
+            faces,
+            bbox: AABB::from_points(min, max).pad(),
+        }
+    }
+}
+
+impl Hittable for BoxShape {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_interval: RealInterval,
+        hit_record: &mut HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> bool {
+        if !self.bbox.hit(ray, t_interval) {
+            return false;
+        }
+
+        let mut hit_anything = false;
+        let mut closest = t_interval.max;
+
+        for face in self.faces.iter() {
+            if face.hit(
+                ray,
+                RealInterval::min_max(t_interval.min, closest),
+                hit_record,
+                rng,
+            ) {
+                hit_anything = true;
+                closest = hit_record.t as f32;
+            }
+        }
+
+        hit_anything
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        &self.bbox
+    }
+
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        let crossings = self
+            .faces
+            .iter()
+            .filter_map(|face| {
+                face.intersection(ray)
+                    .map(|hit| hit.crossing(&face.material))
+            })
+            .collect();
+
+        Some(pair_crossings(crossings))
+    }
+}
+
+/// An intersection of a ray with the surface of a primitive, before it is written in a `HitRecord`.
+struct SurfaceHit {
+    t: f64,
+    /// The unit normal pointing outside of the primitive.
+    outward_normal: Vector3<f64>,
+    u: f64,
+    v: f64,
+    dpdu: Vector3<f64>,
+    dpdv: Vector3<f64>,
+}
+
+impl SurfaceHit {
+    /// Returns the closest hit whose `t` is strictly inside the interval.
+    fn closest(hits: Vec<SurfaceHit>, t_interval: RealInterval) -> Option<SurfaceHit> {
+        hits.into_iter()
+            .filter(|hit| (t_interval.min as f64) < hit.t && hit.t < t_interval.max as f64)
+            .min_by(|a, b| a.t.total_cmp(&b.t))
+    }
+
+    fn record(self, ray: &Ray, material: &Arc<dyn Material>, hit_record: &mut HitRecord) {
+        hit_record.t = self.t;
+        hit_record.hit_point = ray.at(self.t);
+        hit_record.u = self.u;
+        hit_record.v = self.v;
+        hit_record.dpdu = self.dpdu;
+        hit_record.dpdv = self.dpdv;
+        hit_record.material = material.clone();
+        hit_record.vertex_color = None;
+        hit_record.set_face_normal(ray, &self.outward_normal);
+    }
+
+    fn crossing(self, material: &Arc<dyn Material>) -> Crossing {
+        Crossing {
+            t: self.t,
+            outward_normal: self.outward_normal,
+            u: self.u,
+            v: self.v,
+            dpdu: self.dpdu,
+            dpdv: self.dpdv,
+            material: material.clone(),
+        }
+    }
+}
+
+/// The spans inside a closed primitive made of a single material.
+fn solid_spans(hits: Vec<SurfaceHit>, material: &Arc<dyn Material>) -> Vec<Span> {
+    pair_crossings(hits.into_iter().map(|hit| hit.crossing(material)).collect())
+}
+
+/// The angle of the point `(x, z)` around the `y` axis, mapped to `[0, 1]`.
+fn azimuth(x: f64, z: f64) -> f64 {
+    (f64::atan2(-z, x) + std::f64::consts::PI) / (2.0 * std::f64::consts::PI)
+}
+
+/// The derivative of the point `(x, z)` with respect to its `azimuth`.
+fn azimuth_derivative(x: f64, z: f64) -> Vector3<f64> {
+    2.0 * std::f64::consts::PI * Vector3::new(z, 0.0, -x)
+}
+
+/// The derivative of the point `(x, z)` of a disk around the `y` axis, with respect to its distance
+/// to the center relative to the `radius`.
+fn radial_derivative(x: f64, z: f64, radius: f64) -> Vector3<f64> {
+    let distance = (x * x + z * z).sqrt();
+    match distance > 0.0 {
+        true => radius * Vector3::new(x, 0.0, z) / distance,
+        false => Vector3::new(radius, 0.0, 0.0),
+    }
+}
+
+/// A flat disk, defined by its center, the normal of its front face and its radius.
+/// `u` is the angle around the center, and `v` the distance to the center relative to the radius.
+pub struct Disk {
+    center: Point3<f64>,
+    normal: Vector3<f64>,
+    radius: f64,
+    material: Arc<dyn Material>,
+    /// Two unit vectors of the plane of the disk, used for the `u` coordinate.
+    tangent: Vector3<f64>,
+    bitangent: Vector3<f64>,
+    bbox: AABB,
+}
+
+impl Disk {
+    pub fn new(
+        center: Point3<f64>,
+        normal: Vector3<f64>,
+        radius: f64,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        let normal = normal.normalize();
+        let (tangent, bitangent) = orthonormal_basis(&normal);
+
+        // The extent of the disk along an axis depends on the angle between the axis and the normal.
+        let extent = radius
+            * Vector3::new(
+                (1.0 - normal.x * normal.x).max(0.0).sqrt(),
+                (1.0 - normal.y * normal.y).max(0.0).sqrt(),
+                (1.0 - normal.z * normal.z).max(0.0).sqrt(),
+            );
+
+        Self {
+            center,
+            normal,
+            radius,
+            material,
+            tangent,
+            bitangent,
+            bbox: AABB::from_points(center - extent, center + extent).pad(),
+        }
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_interval: RealInterval,
+        hit_record: &mut HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> bool {
+        let denominator = self.normal.dot(ray.direction());
+        if denominator.abs() < 1e-8 {
+            return false;
+        }
+
+        let t = self.normal.dot(&(self.center - ray.origin())) / denominator;
+        let offset = ray.origin() + t * ray.direction() - self.center;
+        let distance = offset.norm();
+        if distance > self.radius {
+            return false;
+        }
+
+        // The derivatives are computed in the basis of the disk, where the normal is the `y` axis.
+        let (x, z) = (offset.dot(&self.tangent), offset.dot(&self.bitangent));
+        let to_world = |d: Vector3<f64>| d.x * self.tangent + d.z * self.bitangent;
+        let hit = SurfaceHit {
+            t,
+            outward_normal: self.normal,
+            u: azimuth(x, z),
+            v: distance / self.radius,
+            dpdu: to_world(azimuth_derivative(x, z)),
+            dpdv: to_world(radial_derivative(x, z, self.radius)),
+        };
+        match SurfaceHit::closest(vec![hit], t_interval) {
+            Some(hit) => {
+                hit.record(ray, &self.material, hit_record);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        &self.bbox
+    }
+}
+
+/// A cylinder standing on a `base` point, along the `y` axis. Use `Transformed` to orient it differently.
+/// On the side, `u` is the angle around the axis and `v` the relative height.
+/// On the caps, `u` is the angle around the axis and `v` the relative distance to the axis.
+pub struct Cylinder {
+    base: Point3<f64>,
+    radius: f64,
+    height: f64,
+    /// If the cylinder is closed by two disks.
+    capped: bool,
+    material: Arc<dyn Material>,
+    bbox: AABB,
+}
+
+impl Cylinder {
+    pub fn new(
+        base: Point3<f64>,
+        radius: f64,
+        height: f64,
+        capped: bool,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        assert!(radius > 0.0 && height > 0.0);
+
+        Self {
+            base,
+            radius,
+            height,
+            capped,
+            material,
+            bbox: AABB::from_points(
+                base - Vector3::new(radius, 0.0, radius),
+                base + Vector3::new(radius, height, radius),
+            ),
+        }
+    }
+
+    /// Every intersection of the ray with the surface of the cylinder, for any `t`.
+    fn intersections(&self, ray: &Ray) -> Vec<SurfaceHit> {
+        let o = ray.origin() - self.base;
+        let d = ray.direction();
+        let mut hits = vec![];
+
+        let roots = solve_quadratic(
+            d.x * d.x + d.z * d.z,
+            2.0 * (o.x * d.x + o.z * d.z),
+            o.x * o.x + o.z * o.z - self.radius * self.radius,
+        );
+        for t in roots {
+            let p = o + t * d;
+            if (0.0..=self.height).contains(&p.y) {
+                hits.push(SurfaceHit {
+                    t,
+                    outward_normal: Vector3::new(p.x, 0.0, p.z) / self.radius,
+                    u: azimuth(p.x, p.z),
+                    v: p.y / self.height,
+                    dpdu: azimuth_derivative(p.x, p.z),
+                    dpdv: Vector3::new(0.0, self.height, 0.0),
+                });
+            }
+        }
+
+        if self.capped && d.y.abs() > 1e-12 {
+            for (cap_y, normal_y) in [(0.0, -1.0), (self.height, 1.0)] {
+                let t = (cap_y - o.y) / d.y;
+                let p = o + t * d;
+                let distance = (p.x * p.x + p.z * p.z).sqrt();
+                if distance <= self.radius {
+                    hits.push(SurfaceHit {
+                        t,
+                        outward_normal: Vector3::new(0.0, normal_y, 0.0),
+                        u: azimuth(p.x, p.z),
+                        v: distance / self.radius,
+                        dpdu: azimuth_derivative(p.x, p.z),
+                        dpdv: radial_derivative(p.x, p.z, self.radius),
+                    });
+                }
+            }
+        }
+
+        hits
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_interval: RealInterval,
+        hit_record: &mut HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> bool {
+        match SurfaceHit::closest(self.intersections(ray), t_interval) {
+            Some(hit) => {
+                hit.record(ray, &self.material, hit_record);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        &self.bbox
+    }
+
+    /// Only capped cylinders enclose a volume.
+    fn is_solid(&self) -> bool {
+        self.capped
+    }
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        match self.capped {
+            true => Some(solid_spans(self.intersections(ray), &self.material)),
+            false => None,
+        }
+    }
+}
+
+/// A cone standing on the center of its `base` disk, with its apex above it along the `y` axis.
+/// Use `Transformed` to orient it differently. The `u` and `v` coordinates are the same as for `Cylinder`.
+pub struct Cone {
+    base: Point3<f64>,
+    radius: f64,
+    height: f64,
+    /// If the cone is closed by its base disk.
+    capped: bool,
+    material: Arc<dyn Material>,
+    bbox: AABB,
+}
+
+impl Cone {
+    pub fn new(
+        base: Point3<f64>,
+        radius: f64,
+        height: f64,
+        capped: bool,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        assert!(radius > 0.0 && height > 0.0);
+
+        Self {
+            base,
+            radius,
+            height,
+            capped,
+            material,
+            bbox: AABB::from_points(
+                base - Vector3::new(radius, 0.0, radius),
+                base + Vector3::new(radius, height, radius),
+            ),
+        }
+    }
+
+    /// Every intersection of the ray with the surface of the cone, for any `t`.
+    fn intersections(&self, ray: &Ray) -> Vec<SurfaceHit> {
+        let o = ray.origin() - self.base;
+        let d = ray.direction();
+        let mut hits = vec![];
+
+        // The radius of the cone at height y is k (h - y).
+        let k = self.radius / self.height;
+        let k2 = k * k;
+        let h = self.height;
+
+        let roots = solve_quadratic(
+            d.x * d.x + d.z * d.z - k2 * d.y * d.y,
+            2.0 * (o.x * d.x + o.z * d.z + k2 * (h - o.y) * d.y),
+            o.x * o.x + o.z * o.z - k2 * (h - o.y) * (h - o.y),
+        );
+        for t in roots {
+            let p = o + t * d;
+            if (0.0..=h).contains(&p.y) {
+                // Going up, the point gets closer to the axis.
+                let radial = radial_derivative(p.x, p.z, 1.0);
+                // The normal is undefined at the apex, where the side is given the normal of the axis.
+                let outward_normal = Vector3::new(p.x, k2 * (h - p.y), p.z)
+                    .try_normalize(0.0)
+                    .unwrap_or(Vector3::new(0.0, 1.0, 0.0));
+                hits.push(SurfaceHit {
+                    t,
+                    outward_normal,
+                    u: azimuth(p.x, p.z),
+                    v: p.y / h,
+                    dpdu: azimuth_derivative(p.x, p.z),
+                    dpdv: h * (Vector3::new(0.0, 1.0, 0.0) - k * radial),
+                });
+            }
+        }
+
+        if self.capped && d.y.abs() > 1e-12 {
+            let t = -o.y / d.y;
+            let p = o + t * d;
+            let distance = (p.x * p.x + p.z * p.z).sqrt();
+            if distance <= self.radius {
+                hits.push(SurfaceHit {
+                    t,
+                    outward_normal: Vector3::new(0.0, -1.0, 0.0),
+                    u: azimuth(p.x, p.z),
+                    v: distance / self.radius,
+                    dpdu: azimuth_derivative(p.x, p.z),
+                    dpdv: radial_derivative(p.x, p.z, self.radius),
+                });
+            }
+        }
+
+        hits
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_interval: RealInterval,
+        hit_record: &mut HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> bool {
+        match SurfaceHit::closest(self.intersections(ray), t_interval) {
+            Some(hit) => {
+                hit.record(ray, &self.material, hit_record);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        &self.bbox
+    }
+
+    /// Only capped cones enclose a volume.
+    fn is_solid(&self) -> bool {
+        self.capped
+    }
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        match self.capped {
+            true => Some(solid_spans(self.intersections(ray), &self.material)),
+            false => None,
+        }
+    }
+}
+
+/// A torus around the `y` axis, defined by the radius of its central circle (`major_radius`)
+/// and the radius of its tube (`minor_radius`). Use `Transformed` to orient it differently.
+/// `u` is the angle around the `y` axis, and `v` the angle around the tube.
+pub struct Torus {
+    center: Point3<f64>,
+    major_radius: f64,
+    minor_radius: f64,
+    material: Arc<dyn Material>,
+    bbox: AABB,
+}
+
+impl Torus {
+    pub fn new(
+        center: Point3<f64>,
+        major_radius: f64,
+        minor_radius: f64,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        assert!(major_radius > 0.0 && minor_radius > 0.0);
+        let extent = Vector3::new(
+            major_radius + minor_radius,
+            minor_radius,
+            major_radius + minor_radius,
+        );
+
+        Self {
+            center,
+            major_radius,
+            minor_radius,
+            material,
+            bbox: AABB::from_points(center - extent, center + extent),
+        }
+    }
+
+    /// Every intersection of the ray with the surface of the torus, for any `t`.
+    fn intersections(&self, ray: &Ray) -> Vec<SurfaceHit> {
+        // The quartic is solved for a unit direction, starting from the point of the ray closest to the center,
+        // to limit the loss of precision.
+        let direction_norm = ray.direction().norm();
+        let d = ray.direction() / direction_norm;
+        let t_start = (self.center - ray.origin()).dot(&d);
+        let o = ray.origin() + t_start * d - self.center;
+
+        let r2 = self.major_radius * self.major_radius;
+        let od = o.dot(&d);
+        let e = o.norm_squared() + r2 - self.minor_radius * self.minor_radius;
+
+        let roots = solve_quartic(
+            1.0,
+            4.0 * od,
+            2.0 * e + 4.0 * od * od - 4.0 * r2 * (d.x * d.x + d.z * d.z),
+            4.0 * od * e - 8.0 * r2 * (o.x * d.x + o.z * d.z),
+            e * e - 4.0 * r2 * (o.x * o.x + o.z * o.z),
+        );
+
+        roots
+            .into_iter()
+            .map(|s| {
+                let p = o + s * d;
+                // The normal points away from the closest point of the central circle.
+                let radial = Vector3::new(p.x, 0.0, p.z).normalize();
+                let tube_offset = radial.dot(&p) - self.major_radius;
+                let outward_normal = (p - self.major_radius * radial).normalize();
+
+                SurfaceHit {
+                    t: (s + t_start) / direction_norm,
+                    outward_normal,
+                    u: azimuth(p.x, p.z),
+                    v: (f64::atan2(p.y, tube_offset) + std::f64::consts::PI)
+                        / (2.0 * std::f64::consts::PI),
+                    dpdu: azimuth_derivative(p.x, p.z),
+                    dpdv: 2.0
+                        * std::f64::consts::PI
+                        * (tube_offset * Vector3::new(0.0, 1.0, 0.0) - p.y * radial),
+                }
+            })
+            .collect()
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_interval: RealInterval,
+        hit_record: &mut HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> bool {
+        match SurfaceHit::closest(self.intersections(ray), t_interval) {
+            Some(hit) => {
+                hit.record(ray, &self.material, hit_record);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        &self.bbox
+    }
+
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        Some(solid_spans(self.intersections(ray), &self.material))
+    }
+}
+
+/// An infinite plane, defined by one of its points and the normal of its front face.
+/// The `u` and `v` coordinates are the position of the hit point on the plane, in world units,
+/// relative to `point`: textures are repeated across the plane.
+pub struct Plane {
+    point: Point3<f64>,
+    normal: Vector3<f64>,
+    material: Arc<dyn Material>,
+    /// Two unit vectors of the plane, used for the `u` and `v` coordinates.
+    tangent: Vector3<f64>,
+    bitangent: Vector3<f64>,
+    bbox: AABB,
+}
+
+impl Plane {
+    pub fn new(point: Point3<f64>, normal: Vector3<f64>, material: Arc<dyn Material>) -> Self {
+        let normal = normal.normalize();
+        let (tangent, bitangent) = orthonormal_basis(&normal);
+
+        Self {
+            point,
+            normal,
+            material,
+            tangent,
+            bitangent,
+            bbox: AABB::infinite(),
+        }
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_interval: RealInterval,
+        hit_record: &mut HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> bool {
+        let denominator = self.normal.dot(ray.direction());
+        if denominator.abs() < 1e-8 {
+            return false;
+        }
+
+        let t = self.normal.dot(&(self.point - ray.origin())) / denominator;
+        if t <= t_interval.min as f64 || t_interval.max as f64 <= t {
+            return false;
+        }
+
+        let offset = ray.origin() + t * ray.direction() - self.point;
+        SurfaceHit {
+            t,
+            outward_normal: self.normal,
+            u: offset.dot(&self.tangent),
+            v: offset.dot(&self.bitangent),
+            dpdu: self.tangent,
+            dpdv: self.bitangent,
+        }
+        .record(ray, &self.material, hit_record);
+
+        true
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        &self.bbox
+    }
+
+    fn is_bounded(&self) -> bool {
+        false
+    }
+
+    /// The plane bounds the half-space behind its front face.
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        let boundary = |t: f64| {
+            let offset = ray.origin() + t * ray.direction() - self.point;
+            Crossing {
+                t,
+                outward_normal: self.normal,
+                u: offset.dot(&self.tangent),
+                v: offset.dot(&self.bitangent),
+                dpdu: self.tangent,
+                dpdv: self.bitangent,
+                material: self.material.clone(),
+            }
+        };
+        let infinity = |t: f64| Crossing {
+            t,
+            outward_normal: self.normal,
+            u: 0.0,
+            v: 0.0,
+            dpdu: self.tangent,
+            dpdv: self.bitangent,
+            material: self.material.clone(),
+        };
+
+        let denominator = self.normal.dot(ray.direction());
+        let distance = self.normal.dot(&(ray.origin() - self.point));
+        if denominator.abs() < 1e-8 {
+            // The ray is parallel to the plane, and either always or never inside the half-space.
+            return Some(match distance < 0.0 {
+                true => vec![Span {
+                    enter: infinity(f64::NEG_INFINITY),
+                    exit: infinity(f64::INFINITY),
+                }],
+                false => vec![],
+            });
+        }
+
+        let t = -distance / denominator;
+        Some(vec![match denominator < 0.0 {
+            true => Span {
+                enter: boundary(t),
+                exit: infinity(f64::INFINITY),
+            },
+            false => Span {
+                enter: infinity(f64::NEG_INFINITY),
+                exit: boundary(t),
+            },
+        }])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::default())
+    }
+
+    fn intervals(hittable: &impl Hittable, ray: &Ray) -> Vec<(f64, f64)> {
+        hittable
+            .spans(ray)
+            .unwrap()
+            .iter()
+            .map(|span| (span.enter.t, span.exit.t))
+            .collect()
+    }
+
+    #[test]
+    fn spans_through_box_edge() {
+        let unit_box = BoxShape::new(Point3::origin(), Point3::new(1.0, 1.0, 1.0), material());
+
+        // Enters through the edge shared by the left and back faces, leaves through the right face.
+        let ray = Ray::new(
+            Point3::new(-1.0, 0.5, -0.5),
+            Vector3::new(1.0, 0.0, 0.5),
+            0.0,
+        );
+        assert_eq!(intervals(&unit_box, &ray), [(1.0, 2.0)]);
+
+        // Only touches the edge.
+        let ray = Ray::new(
+            Point3::new(-1.0, 0.5, 1.0),
+            Vector3::new(1.0, 0.0, -1.0),
+            0.0,
+        );
+        assert!(intervals(&unit_box, &ray).is_empty());
+    }
+
+    #[test]
+    fn spans_through_cone_apex() {
+        let cone = Cone::new(Point3::origin(), 1.0, 1.0, true, material());
+
+        // Enters through the apex, and leaves through the base.
+        let ray = Ray::new(
+            Point3::new(0.0, 2.0, 0.0),
+            Vector3::new(0.0, -1.0, 0.0),
+            0.0,
+        );
+        let intervals = intervals(&cone, &ray);
+        assert_eq!(intervals.len(), 1);
+        assert!((intervals[0].0 - 1.0).abs() < 1e-9 && (intervals[0].1 - 2.0).abs() < 1e-9);
+    }
+
+    fn hit(
+        hittable: &impl Hittable,
+        origin: Point3<f64>,
+        direction: Vector3<f64>,
+    ) -> Option<HitRecord> {
+        let ray = Ray::new(origin, direction, 0.0);
+        let mut hit_record = HitRecord::default();
+        hittable
+            .hit(
+                &ray,
+                RealInterval::min_max(0.001, f32::INFINITY),
+                &mut hit_record,
+                &mut rand::thread_rng(),
+            )
+            .then_some(hit_record)
+    }
+
+    #[test]
+    fn axis_aligned_quad() {
+        let quad = Quad::new(
+            Point3::new(0.0, 0.0, 2.0),
+            Vector3::new(2.0, 0.0, 0.0),
+            Vector3::new(0.0, 3.0, 0.0),
+            material(),
+        );
+
+        // The box is padded along `z` only, so that a ray along `z` still hits it.
+        let bbox = quad.bounding_box();
+        assert_eq!((bbox.x.min, bbox.x.max), (0.0, 2.0));
+        assert_eq!((bbox.y.min, bbox.y.max), (0.0, 3.0));
+        assert!(bbox.z.min < 2.0 && 2.0 < bbox.z.max && bbox.z.max - bbox.z.min < 1e-3);
+        let ray = Ray::new(
+            Point3::new(0.5, 1.5, 5.0),
+            Vector3::new(0.0, 0.0, -1.0),
+            0.0,
+        );
+        assert!(bbox.hit(&ray, RealInterval::min_max(0.001, f32::INFINITY)));
+
+        let hit_record = hit(&quad, *ray.origin(), *ray.direction()).unwrap();
+        assert!((hit_record.t - 3.0).abs() < 1e-12);
+        assert!((hit_record.u - 0.25).abs() < 1e-12 && (hit_record.v - 0.5).abs() < 1e-12);
+        assert_eq!(hit_record.normal, Vector3::new(0.0, 0.0, 1.0));
+        assert!(hit_record.front_face);
+
+        assert!(hit(&quad, Point3::new(2.5, 1.5, 5.0), *ray.direction()).is_none());
+    }
+
+    #[test]
+    fn triangle_barycentric_coordinates() {
+        let triangle = Triangle::new(
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(3.0, 0.0, 0.0),
+            Point3::new(1.0, 2.0, 0.0),
+            material(),
+        );
+        let down = Vector3::new(0.0, 0.0, -1.0);
+
+        let hit_record = hit(&triangle, Point3::new(1.4, 0.6, 1.0), down).unwrap();
+        assert!((hit_record.t - 1.0).abs() < 1e-12);
+        assert!((hit_record.u - 0.2).abs() < 1e-12 && (hit_record.v - 0.3).abs() < 1e-12);
+        assert_eq!(hit_record.normal, Vector3::new(0.0, 0.0, 1.0));
+
+        // Outside of the triangle, beyond the edge from `p1` to `p2`.
+        assert!(hit(&triangle, Point3::new(2.1, 1.0, 1.0), down).is_none());
+    }
+
+    #[test]
+    fn box_face_materials() {
+        let materials: [Arc<dyn Material>; 6] = std::array::from_fn(|_| material());
+        let cube = BoxShape::with_face_materials(
+            Point3::new(-1.0, -1.0, -1.0),
+            Point3::new(1.0, 1.0, 1.0),
+            materials.clone(),
+        );
+
+        let normals = [
+            Vector3::new(0.0, 0.0, 1.0),
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(0.0, 0.0, -1.0),
+            Vector3::new(-1.0, 0.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(0.0, -1.0, 0.0),
+        ];
+        for (normal, material) in normals.iter().zip(&materials) {
+            // From outside, slightly off the center of the face to stay away from the edges of the other faces.
+            let offset = Vector3::new(0.1, 0.2, 0.3);
+            let origin = Point3::from(3.0 * normal) + offset - offset.dot(normal) * normal;
+            let hit_record = hit(&cube, origin, -normal).unwrap();
+            assert!((hit_record.t - 2.0).abs() < 1e-12);
+            assert_eq!(hit_record.normal, *normal);
+            assert!(hit_record.front_face);
+            assert!(Arc::ptr_eq(&hit_record.material, material));
+
+            // From inside, the same face is hit on its back.
+            let hit_record = hit(&cube, Point3::origin(), *normal).unwrap();
+            assert_eq!(hit_record.normal, -normal);
+            assert!(!hit_record.front_face);
+            assert!(Arc::ptr_eq(&hit_record.material, material));
+        }
+    }
 }
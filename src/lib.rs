@@ -6,17 +6,27 @@ use rayon::prelude::*;
 use std::time::Instant;
 
 use camera::Camera;
-use ray::Ray;
+use ray::{Ray, RayDifferential};
 use world::World;
 
 mod aabb;
+pub mod background;
 pub mod bvh;
 pub mod camera;
+pub mod csg;
+pub mod environment;
 pub mod geometry;
+pub mod loader;
 pub mod material;
-mod ray;
+pub mod mesh;
+pub mod microfacet;
+pub mod noise;
+pub mod ray;
+pub mod sdf;
 pub mod texture;
+pub mod transform;
 mod utility;
+pub mod volume;
 pub mod world;
 
 /// A structure encapsulating elements to render a scene.
@@ -197,7 +207,17 @@ impl Renderer {
 
         let time = rng.gen();
 
-        Ray::new(origin, ray_direction, time)
+        // The rays through the neighbouring pixels. With several samples per pixel, each sample covers
+        // a smaller area, so the offsets are scaled down.
+        let scale = (1.0 / (self.camera.samples_per_pixel as f64).sqrt()).max(0.125);
+        let differential = RayDifferential {
+            x_origin: origin,
+            x_direction: ray_direction + scale * self.pixel_delta_u,
+            y_origin: origin,
+            y_direction: ray_direction + scale * self.pixel_delta_v,
+        };
+
+        Ray::new(origin, ray_direction, time).with_differential(differential)
     }
 
     /// Generates a vector from the center of the pixel to a random point of the square pixel.
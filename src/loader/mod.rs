@@ -0,0 +1,66 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+pub mod obj;
+pub mod ply;
+pub mod vol;
+
+/// An error encountered while loading a scene file.
+#[derive(Debug)]
+pub enum LoadError {
+    /// The file could not be read.
+    Io(PathBuf, std::io::Error),
+    /// An image referenced by the file could not be loaded.
+    Image(PathBuf, image::ImageError),
+    /// The content of the file is malformed, outside of a text line.
+    Invalid(PathBuf, String),
+    /// The content of the file is malformed. Lines are numbered from 1.
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+impl LoadError {
+    pub(crate) fn parse(path: &Path, line: usize, message: impl Into<String>) -> Self {
+        Self::Parse {
+            path: path.to_path_buf(),
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, error) => write!(f, "{}: {error}", path.display()),
+            Self::Image(path, error) => write!(f, "{}: {error}", path.display()),
+            Self::Invalid(path, message) => write!(f, "{}: {message}", path.display()),
+            Self::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{line}: {message}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(_, error) => Some(error),
+            Self::Image(_, error) => Some(error),
+            Self::Invalid(..) | Self::Parse { .. } => None,
+        }
+    }
+}
+
+/// Writes `contents` to a file of the temporary directory, named after the test using it.
+#[cfg(test)]
+pub(crate) fn temporary_file(name: &str, contents: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("{}-{name}", std::process::id()));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
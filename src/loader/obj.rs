@@ -0,0 +1,475 @@
+//! A loader for Wavefront `.obj` meshes, and the `.mtl` material libraries they reference.
+
+use nalgebra::{Point3, Vector3};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::LoadError;
+use crate::bvh::BVHNode;
+use crate::material::{Dielectric, Lambertian, Material, Metal, TexturedLambertian};
+use crate::mesh::TriangleMesh;
+use crate::texture::Texture;
+
+/// Loads a `.obj` file and builds a Bounding Volume Hierarchy over all its faces.
+pub fn load(path: impl AsRef<Path>) -> Result<BVHNode, LoadError> {
+    let path = path.as_ref();
+    let meshes = load_meshes(path)?;
+    if meshes.is_empty() {
+        return Err(LoadError::Invalid(
+            path.to_path_buf(),
+            "the file does not contain any face".to_string(),
+        ));
+    }
+
+    Ok(BVHNode::from_objects(
+        meshes
+            .into_iter()
+            .flat_map(TriangleMesh::into_triangles)
+            .map(|triangle| Box::new(triangle) as _)
+            .collect(),
+    ))
+}
+
+/// Loads a `.obj` file as a list of meshes, one for each group and material of the file.
+/// Polygonal faces are triangulated as fans, and are therefore assumed to be convex.
+/// Vertex colors, written after the positions, are loaded: use the `VertexColor` material to render them.
+pub fn load_meshes(path: impl AsRef<Path>) -> Result<Vec<TriangleMesh>, LoadError> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|error| LoadError::Io(path.to_path_buf(), error))?;
+
+    let mut parser = ObjParser::new(path);
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|error| LoadError::Io(path.to_path_buf(), error))?;
+        parser.parse_line(&line, index + 1)?;
+    }
+
+    Ok(parser.into_meshes())
+}
+
+/// A reference to a vertex of a face: indices of its position, texture coordinates and normal.
+type VertexIndices = (usize, Option<usize>, Option<usize>);
+
+/// A set of faces sharing the same group and material.
+struct Chunk {
+    material: Arc<dyn Material>,
+    faces: Vec<[VertexIndices; 3]>,
+}
+
+struct ObjParser<'a> {
+    path: &'a Path,
+    positions: Vec<Point3<f64>>,
+    /// The colors following the positions in the `v x y z r g b` extension of the format.
+    colors: Vec<Option<Vector3<f64>>>,
+    texture_coordinates: Vec<(f64, f64)>,
+    normals: Vec<Vector3<f64>>,
+    materials: HashMap<String, Arc<dyn Material>>,
+    /// Images already loaded, shared between the materials referencing them.
+    textures: HashMap<PathBuf, Texture>,
+    chunks: Vec<Chunk>,
+    current_material: Arc<dyn Material>,
+}
+
+impl<'a> ObjParser<'a> {
+    fn new(path: &'a Path) -> Self {
+        Self {
+            path,
+            positions: vec![],
+            colors: vec![],
+            texture_coordinates: vec![],
+            normals: vec![],
+            materials: HashMap::new(),
+            textures: HashMap::new(),
+            chunks: vec![],
+            current_material: Arc::new(Lambertian::default()),
+        }
+    }
+
+    fn parse_line(&mut self, line: &str, line_number: usize) -> Result<(), LoadError> {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            return Ok(());
+        };
+        let arguments: Vec<&str> = tokens.collect();
+        let error = |message: String| LoadError::parse(self.path, line_number, message);
+
+        match keyword {
+            "v" => {
+                // An optional `w` coordinate may follow the position, and an optional color may end the line.
+                let values = parse_floats(&arguments, 3, 7).map_err(error)?;
+                self.positions
+                    .push(Point3::new(values[0], values[1], values[2]));
+                self.colors.push(match values.len() {
+                    6 | 7 => Some(Vector3::from_column_slice(&values[values.len() - 3..])),
+                    _ => None,
+                });
+            }
+            "vt" => {
+                let coordinates = parse_floats(&arguments, 1, 3).map_err(error)?;
+                let v = coordinates.get(1).copied().unwrap_or_default();
+                self.texture_coordinates.push((coordinates[0], v));
+            }
+            "vn" => {
+                let [x, y, z] = parse_floats(&arguments, 3, 3).map_err(error)?[..] else {
+                    unreachable!()
+                };
+                self.normals.push(Vector3::new(x, y, z));
+            }
+            "f" => {
+                if arguments.len() < 3 {
+                    return Err(error(format!(
+                        "a face needs at least 3 vertices, found {}",
+                        arguments.len()
+                    )));
+                }
+
+                let vertices = arguments
+                    .iter()
+                    .map(|vertex| self.parse_vertex(vertex))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(error)?;
+
+                if self.chunks.is_empty() {
+                    self.start_chunk();
+                }
+                let faces = &mut self.chunks.last_mut().unwrap().faces;
+                // Fan triangulation of the polygon
+                for i in 1..vertices.len() - 1 {
+                    faces.push([vertices[0], vertices[i], vertices[i + 1]]);
+                }
+            }
+            "g" | "o" => self.start_chunk(),
+            "usemtl" => {
+                let name = arguments.join(" ");
+                self.current_material = self
+                    .materials
+                    .get(&name)
+                    .ok_or_else(|| error(format!("unknown material `{name}`")))?
+                    .clone();
+                self.start_chunk();
+            }
+            "mtllib" => {
+                for library in arguments {
+                    let library_path = self.resolve(library);
+                    self.load_material_library(&library_path)?;
+                }
+            }
+            // Smoothing groups, lines, points and other statements are ignored
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Parses a face vertex, in one of the `v`, `v/vt`, `v//vn` or `v/vt/vn` forms.
+    fn parse_vertex(&self, vertex: &str) -> Result<VertexIndices, String> {
+        let mut indices = vertex.split('/');
+
+        let position = resolve_index(indices.next().unwrap_or_default(), self.positions.len())?;
+        let texture_coordinates = match indices.next() {
+            Some("") | None => None,
+            Some(index) => Some(resolve_index(index, self.texture_coordinates.len())?),
+        };
+        let normal = match indices.next() {
+            Some("") | None => None,
+            Some(index) => Some(resolve_index(index, self.normals.len())?),
+        };
+
+        Ok((position, texture_coordinates, normal))
+    }
+
+    /// Starts a new set of faces, using the current material.
+    fn start_chunk(&mut self) {
+        match self.chunks.last_mut() {
+            Some(chunk) if chunk.faces.is_empty() => {
+                chunk.material = self.current_material.clone();
+            }
+            _ => self.chunks.push(Chunk {
+                material: self.current_material.clone(),
+                faces: vec![],
+            }),
+        }
+    }
+
+    /// Resolves a path relative to the directory of the `.obj` file.
+    fn resolve(&self, file: &str) -> PathBuf {
+        self.path
+            .parent()
+            .map(|directory| directory.join(file))
+            .unwrap_or_else(|| PathBuf::from(file))
+    }
+
+    /// Parses a `.mtl` file, and adds its materials to the list of known materials.
+    fn load_material_library(&mut self, path: &Path) -> Result<(), LoadError> {
+        let file = File::open(path).map_err(|error| LoadError::Io(path.to_path_buf(), error))?;
+
+        let mut current: Option<(String, MtlDescription)> = None;
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|error| LoadError::Io(path.to_path_buf(), error))?;
+            let line = line.split('#').next().unwrap_or_default();
+            let mut tokens = line.split_whitespace();
+            let Some(keyword) = tokens.next() else {
+                continue;
+            };
+            let arguments: Vec<&str> = tokens.collect();
+            let error = |message: String| LoadError::parse(path, index + 1, message);
+
+            if keyword == "newmtl" {
+                if let Some((name, description)) = current.take() {
+                    let material = self.build_material(&description)?;
+                    self.materials.insert(name, material);
+                }
+                current = Some((arguments.join(" "), MtlDescription::default()));
+                continue;
+            }
+
+            let Some((_, description)) = current.as_mut() else {
+                return Err(error(format!("`{keyword}` statement before any `newmtl`")));
+            };
+            match keyword {
+                "Kd" => description.diffuse = parse_color(&arguments).map_err(error)?,
+                "Ks" => description.specular = parse_color(&arguments).map_err(error)?,
+                "Ns" => description.shininess = parse_floats(&arguments, 1, 1).map_err(error)?[0],
+                "Ni" => description.index = parse_floats(&arguments, 1, 1).map_err(error)?[0],
+                "d" => description.dissolve = parse_floats(&arguments, 1, 1).map_err(error)?[0],
+                "Tr" => {
+                    description.dissolve = 1.0 - parse_floats(&arguments, 1, 1).map_err(error)?[0]
+                }
+                "map_Kd" => {
+                    // Texture options, such as `-s` or `-o`, are not supported: only the file name is kept.
+                    let file = arguments
+                        .last()
+                        .ok_or_else(|| error("missing texture file name".to_string()))?;
+                    description.diffuse_map = Some(self.resolve(file));
+                }
+                _ => {}
+            }
+        }
+
+        if let Some((name, description)) = current {
+            let material = self.build_material(&description)?;
+            self.materials.insert(name, material);
+        }
+
+        Ok(())
+    }
+
+    /// Converts a material description to the closest material of the crate.
+    fn build_material(
+        &mut self,
+        description: &MtlDescription,
+    ) -> Result<Arc<dyn Material>, LoadError> {
+        if description.dissolve < 1.0 {
+            return Ok(Arc::new(Dielectric::new(description.index)));
+        }
+
+        if let Some(map_path) = &description.diffuse_map {
+            let texture = match self.textures.get(map_path) {
+                Some(texture) => texture.clone(),
+                None => {
+                    let texture = Texture::load_image(map_path)
+                        .map_err(|error| LoadError::Image(map_path.clone(), error))?;
+                    self.textures.insert(map_path.clone(), texture.clone());
+                    texture
+                }
+            };
+            return Ok(Arc::new(TexturedLambertian::new(texture)));
+        }
+
+        if description.specular.max() > description.diffuse.max() {
+            // Converts the Phong exponent to a roughness.
+            let fuzz = (2.0 / (description.shininess + 2.0)).sqrt();
+            return Ok(Arc::new(Metal::new(description.specular, fuzz)));
+        }
+
+        Ok(Arc::new(Lambertian::new(description.diffuse)))
+    }
+
+    /// Converts the parsed faces to meshes, with their own vertex lists.
+    fn into_meshes(self) -> Vec<TriangleMesh> {
+        self.chunks
+            .into_iter()
+            .filter(|chunk| !chunk.faces.is_empty())
+            .map(|chunk| {
+                let mut vertex_ids: HashMap<VertexIndices, usize> = HashMap::new();
+                let mut vertices: Vec<VertexIndices> = vec![];
+                let indices = chunk
+                    .faces
+                    .iter()
+                    .map(|face| {
+                        face.map(|vertex| {
+                            *vertex_ids.entry(vertex).or_insert_with(|| {
+                                vertices.push(vertex);
+                                vertices.len() - 1
+                            })
+                        })
+                    })
+                    .collect();
+
+                let positions = vertices.iter().map(|v| self.positions[v.0]).collect();
+                let mut mesh = TriangleMesh::new(positions, indices, chunk.material);
+
+                // Normals, texture coordinates and colors are only used if every vertex has them.
+                if let Some(uvs) = vertices
+                    .iter()
+                    .map(|v| v.1.map(|i| self.texture_coordinates[i]))
+                    .collect()
+                {
+                    mesh.set_uvs(uvs);
+                }
+                if let Some(normals) = vertices
+                    .iter()
+                    .map(|v| v.2.map(|i| self.normals[i]))
+                    .collect()
+                {
+                    mesh.set_normals(normals);
+                }
+                if let Some(colors) = vertices.iter().map(|v| self.colors[v.0]).collect() {
+                    mesh.set_colors(colors);
+                }
+
+                mesh
+            })
+            .collect()
+    }
+}
+
+/// The properties of a material, as described in a `.mtl` file.
+struct MtlDescription {
+    diffuse: Vector3<f64>,
+    specular: Vector3<f64>,
+    shininess: f64,
+    index: f64,
+    dissolve: f64,
+    diffuse_map: Option<PathBuf>,
+}
+
+impl Default for MtlDescription {
+    fn default() -> Self {
+        Self {
+            diffuse: Vector3::new(0.8, 0.8, 0.8),
+            specular: Vector3::zeros(),
+            shininess: 0.0,
+            index: 1.5,
+            dissolve: 1.0,
+            diffuse_map: None,
+        }
+    }
+}
+
+/// Parses between `min` and `max` floating point numbers.
+fn parse_floats(arguments: &[&str], min: usize, max: usize) -> Result<Vec<f64>, String> {
+    if arguments.len() < min || arguments.len() > max {
+        return Err(match min == max {
+            true => format!("expected {min} numbers, found {}", arguments.len()),
+            false => format!(
+                "expected between {min} and {max} numbers, found {}",
+                arguments.len()
+            ),
+        });
+    }
+
+    arguments
+        .iter()
+        .map(|argument| {
+            argument
+                .parse()
+                .map_err(|_| format!("invalid number `{argument}`"))
+        })
+        .collect()
+}
+
+/// Parses an RGB color. A single component is used for the three channels.
+fn parse_color(arguments: &[&str]) -> Result<Vector3<f64>, String> {
+    let components = parse_floats(arguments, 1, 3)?;
+    match components[..] {
+        [gray] => Ok(Vector3::new(gray, gray, gray)),
+        [r, g, b] => Ok(Vector3::new(r, g, b)),
+        _ => Err("expected 1 or 3 color components".to_string()),
+    }
+}
+
+/// Converts a 1-based, or negative relative, `.obj` index to a 0-based index in a list of `len` elements.
+fn resolve_index(index: &str, len: usize) -> Result<usize, String> {
+    let value: i64 = index
+        .parse()
+        .map_err(|_| format!("invalid index `{index}`"))?;
+
+    let resolved = match value {
+        0 => None,
+        value if value > 0 => Some(value as usize - 1),
+        value => len.checked_sub(value.unsigned_abs() as usize),
+    };
+
+    resolved
+        .filter(|&i| i < len)
+        .ok_or_else(|| format!("index {value} is out of range, only {len} elements are defined"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::temporary_file;
+
+    fn load_str(name: &str, contents: &str) -> Result<Vec<TriangleMesh>, LoadError> {
+        load_meshes(temporary_file(name, contents.as_bytes()))
+    }
+
+    #[test]
+    fn triangle() {
+        let meshes = load_str("triangle.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].len(), 1);
+    }
+
+    #[test]
+    fn quad_is_triangulated() {
+        let file = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n";
+        let meshes = load_str("quad.obj", file).unwrap();
+        assert_eq!(meshes[0].len(), 2);
+    }
+
+    #[test]
+    fn negative_indices() {
+        let file = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf -3//-1 -2//-1 -1//-1\n";
+        let meshes = load_str("negative_indices.obj", file).unwrap();
+        assert_eq!(meshes[0].len(), 1);
+
+        assert!(load_str(
+            "negative_out_of_range.obj",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf -4 -2 -1\n"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn vertex_colors() {
+        let file = "v 0 0 0 1 0 0\nv 1 0 0 0 1 0\nv 0 1 0 1.0 0 0 1\nf 1 2 3\n";
+        assert!(load_str("vertex_colors.obj", file).is_ok());
+    }
+
+    #[test]
+    fn malformed() {
+        for (name, file, line) in [
+            ("short_vertex.obj", "v 0 0\n", 1),
+            ("long_vertex.obj", "v 0 0 0 1 1 1 1 1\n", 1),
+            ("invalid_number.obj", "v 0 x 0\n", 1),
+            ("short_face.obj", "v 0 0 0\nv 1 0 0\nf 1 2\n", 3),
+            ("zero_index.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 0 1 2\n", 4),
+            ("missing_vertex.obj", "v 0 0 0\nv 1 0 0\nf 1 2 3\n", 3),
+        ] {
+            assert!(
+                matches!(load_str(name, file), Err(LoadError::Parse { line: l, .. }) if l == line),
+                "{name}"
+            );
+        }
+
+        assert!(matches!(
+            load(temporary_file("no_face.obj", b"v 0 0 0\n")),
+            Err(LoadError::Invalid(..))
+        ));
+    }
+}
@@ -0,0 +1,636 @@
+//! A loader for Stanford `.ply` meshes, in ASCII or binary (little or big endian) format.
+
+use nalgebra::{Point3, Vector3};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+use super::LoadError;
+use crate::material::Material;
+use crate::mesh::TriangleMesh;
+
+/// Loads a `.ply` file as a mesh using the given material.
+/// Vertex normals, texture coordinates and colors are loaded when present:
+/// use the `VertexColor` material to render the colors. Polygonal faces are triangulated as fans.
+pub fn load(
+    path: impl AsRef<Path>,
+    material: Arc<dyn Material>,
+) -> Result<TriangleMesh, LoadError> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|error| LoadError::Io(path.to_path_buf(), error))?;
+    let file_size = file
+        .metadata()
+        .map_err(|error| LoadError::Io(path.to_path_buf(), error))?
+        .len();
+    let mut reader = BufReader::new(file);
+
+    let header = Header::parse(&mut reader, path)?;
+    // Each instance takes at least one byte, which rejects absurd counts before reading anything.
+    if let Some(element) = header
+        .elements
+        .iter()
+        .find(|element| element.count as u64 > file_size)
+    {
+        return Err(LoadError::Invalid(
+            path.to_path_buf(),
+            format!(
+                "the header declares {} instances of `{}`, but the file has only {file_size} bytes",
+                element.count, element.name
+            ),
+        ));
+    }
+    let vertex_count = header
+        .elements
+        .iter()
+        .find(|element| element.name == "vertex")
+        .map_or(0, |element| element.count);
+    let mut body = BodyReader {
+        reader,
+        path,
+        format: header.format,
+        line_number: header.line_count,
+        tokens: vec![],
+        next_token: 0,
+    };
+
+    let mut vertices = VertexData::default();
+    let mut indices: Vec<[usize; 3]> = vec![];
+    let mut values: Vec<Vec<f64>> = vec![];
+
+    for element in &header.elements {
+        match element.name.as_str() {
+            "vertex" => vertices = VertexData::read(&mut body, element, &mut values)?,
+            "face" => {
+                let Some(property) = element.index_of(&["vertex_indices", "vertex_index"]) else {
+                    return Err(LoadError::Invalid(
+                        path.to_path_buf(),
+                        "the `face` element has no `vertex_indices` property".to_string(),
+                    ));
+                };
+
+                for _ in 0..element.count {
+                    body.read_instance(element, &mut values)?;
+                    let polygon = values[property]
+                        .iter()
+                        .map(|&index| match as_index(index) {
+                            Some(index) if index < vertex_count => Ok(index),
+                            _ => Err(body.error(format!(
+                                "invalid vertex index {index}, {vertex_count} vertices are declared"
+                            ))),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+
+                    // Fan triangulation of the polygon
+                    for i in 1..polygon.len().saturating_sub(1) {
+                        indices.push([polygon[0], polygon[i], polygon[i + 1]]);
+                    }
+                }
+            }
+            // Other elements, such as edges, are read and ignored
+            _ => {
+                for _ in 0..element.count {
+                    body.read_instance(element, &mut values)?;
+                }
+            }
+        }
+    }
+
+    if indices.is_empty() {
+        return Err(LoadError::Invalid(
+            path.to_path_buf(),
+            "the file does not contain any face".to_string(),
+        ));
+    }
+    if vertices.positions.len() != vertex_count {
+        return Err(LoadError::Invalid(
+            path.to_path_buf(),
+            "the file has several `vertex` elements".to_string(),
+        ));
+    }
+
+    let mut mesh = TriangleMesh::new(vertices.positions, indices, material);
+    if let Some(normals) = vertices.normals {
+        mesh.set_normals(normals);
+    }
+    if let Some(uvs) = vertices.uvs {
+        mesh.set_uvs(uvs);
+    }
+    if let Some(colors) = vertices.colors {
+        mesh.set_colors(colors);
+    }
+
+    Ok(mesh)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum ScalarType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "char" | "int8" => Some(Self::Int8),
+            "uchar" | "uint8" => Some(Self::UInt8),
+            "short" | "int16" => Some(Self::Int16),
+            "ushort" | "uint16" => Some(Self::UInt16),
+            "int" | "int32" => Some(Self::Int32),
+            "uint" | "uint32" => Some(Self::UInt32),
+            "float" | "float32" => Some(Self::Float32),
+            "double" | "float64" => Some(Self::Float64),
+            _ => None,
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Self::Int8 | Self::UInt8 => 1,
+            Self::Int16 | Self::UInt16 => 2,
+            Self::Int32 | Self::UInt32 | Self::Float32 => 4,
+            Self::Float64 => 8,
+        }
+    }
+
+    /// The value mapped to a full intensity, when the type is used for a color channel.
+    fn color_scale(&self) -> f64 {
+        match self {
+            Self::Int8 | Self::UInt8 => 255.0,
+            Self::Int16 | Self::UInt16 => 65535.0,
+            _ => 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum PropertyType {
+    Scalar(ScalarType),
+    /// A list of values, preceded by their number.
+    List {
+        count: ScalarType,
+        item: ScalarType,
+    },
+}
+
+struct Property {
+    name: String,
+    property_type: PropertyType,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    /// Returns the index of the first property having one of the given names.
+    fn index_of(&self, names: &[&str]) -> Option<usize> {
+        self.properties
+            .iter()
+            .position(|property| names.contains(&property.name.as_str()))
+    }
+
+    /// Returns the index and type of the first scalar property having one of the given names.
+    fn scalar(&self, names: &[&str]) -> Option<(usize, ScalarType)> {
+        let index = self.index_of(names)?;
+        match self.properties[index].property_type {
+            PropertyType::Scalar(scalar_type) => Some((index, scalar_type)),
+            PropertyType::List { .. } => None,
+        }
+    }
+}
+
+struct Header {
+    format: Format,
+    elements: Vec<Element>,
+    /// The number of lines of the header, used to number the lines of ASCII files.
+    line_count: usize,
+}
+
+impl Header {
+    fn parse(reader: &mut impl BufRead, path: &Path) -> Result<Self, LoadError> {
+        let mut format = None;
+        let mut elements: Vec<Element> = vec![];
+        let mut line = String::new();
+        let mut line_number = 0;
+
+        loop {
+            line.clear();
+            let read = reader
+                .read_line(&mut line)
+                .map_err(|error| LoadError::Io(path.to_path_buf(), error))?;
+            line_number += 1;
+            let error = |message: &str| LoadError::parse(path, line_number, message);
+
+            if read == 0 {
+                return Err(error("unexpected end of file in the header"));
+            }
+
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if line_number == 1 {
+                if tokens != ["ply"] {
+                    return Err(error("not a PLY file, the first line must be `ply`"));
+                }
+                continue;
+            }
+
+            match tokens[..] {
+                ["format", name, _version] => {
+                    format = Some(match name {
+                        "ascii" => Format::Ascii,
+                        "binary_little_endian" => Format::BinaryLittleEndian,
+                        "binary_big_endian" => Format::BinaryBigEndian,
+                        _ => return Err(error(&format!("unknown format `{name}`"))),
+                    });
+                }
+                ["element", name, count] => elements.push(Element {
+                    name: name.to_string(),
+                    count: count
+                        .parse()
+                        .map_err(|_| error(&format!("invalid element count `{count}`")))?,
+                    properties: vec![],
+                }),
+                ["property", "list", count, item, name] => {
+                    let property_type = PropertyType::List {
+                        count: ScalarType::parse(count)
+                            .ok_or_else(|| error(&format!("unknown type `{count}`")))?,
+                        item: ScalarType::parse(item)
+                            .ok_or_else(|| error(&format!("unknown type `{item}`")))?,
+                    };
+                    elements
+                        .last_mut()
+                        .ok_or_else(|| error("property declared before any element"))?
+                        .properties
+                        .push(Property {
+                            name: name.to_string(),
+                            property_type,
+                        });
+                }
+                ["property", scalar_type, name] => {
+                    let property_type = PropertyType::Scalar(
+                        ScalarType::parse(scalar_type)
+                            .ok_or_else(|| error(&format!("unknown type `{scalar_type}`")))?,
+                    );
+                    elements
+                        .last_mut()
+                        .ok_or_else(|| error("property declared before any element"))?
+                        .properties
+                        .push(Property {
+                            name: name.to_string(),
+                            property_type,
+                        });
+                }
+                ["end_header"] => break,
+                ["comment", ..] | ["obj_info", ..] | [] => {}
+                _ => return Err(error(&format!("invalid header line `{}`", line.trim()))),
+            }
+        }
+
+        Ok(Self {
+            format: format.ok_or_else(|| {
+                LoadError::parse(path, line_number, "missing `format` line in the header")
+            })?,
+            elements,
+            line_count: line_number,
+        })
+    }
+}
+
+/// Reads the values of the elements following the header.
+struct BodyReader<'a, R> {
+    reader: R,
+    path: &'a Path,
+    format: Format,
+    /// The number of the last line read, in ASCII files.
+    line_number: usize,
+    /// The remaining tokens of the current line, in ASCII files.
+    tokens: Vec<String>,
+    next_token: usize,
+}
+
+impl<'a, R: BufRead> BodyReader<'a, R> {
+    /// Reads an instance of the given element. `values` receives the values of each property.
+    fn read_instance(
+        &mut self,
+        element: &Element,
+        values: &mut Vec<Vec<f64>>,
+    ) -> Result<(), LoadError> {
+        values.resize(element.properties.len(), vec![]);
+
+        for (property, property_values) in element.properties.iter().zip(values.iter_mut()) {
+            property_values.clear();
+            let result = match property.property_type {
+                PropertyType::Scalar(scalar_type) => self
+                    .read(scalar_type)
+                    .map(|value| property_values.push(value)),
+                PropertyType::List { count, item } => self.read(count).and_then(|len| {
+                    let len = as_index(len).ok_or_else(|| format!("invalid list length {len}"))?;
+                    for _ in 0..len {
+                        property_values.push(self.read(item)?);
+                    }
+                    Ok(())
+                }),
+            };
+
+            result.map_err(|message| {
+                self.error(format!(
+                    "{message}, in property `{}` of an element `{}`",
+                    property.name, element.name
+                ))
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// An error at the current position: on the current line of ASCII files, or anywhere in binary files.
+    fn error(&self, message: String) -> LoadError {
+        match self.format {
+            Format::Ascii => LoadError::parse(self.path, self.line_number, message),
+            _ => LoadError::Invalid(self.path.to_path_buf(), message),
+        }
+    }
+
+    fn read(&mut self, scalar_type: ScalarType) -> Result<f64, String> {
+        match self.format {
+            Format::Ascii => {
+                let token = self.next_token()?;
+                token
+                    .parse()
+                    .map_err(|_| format!("invalid number `{token}`"))
+            }
+            Format::BinaryLittleEndian | Format::BinaryBigEndian => {
+                let mut bytes = [0u8; 8];
+                let size = scalar_type.size();
+                self.reader
+                    .read_exact(&mut bytes[..size])
+                    .map_err(|error| error.to_string())?;
+
+                // Convert to little endian before decoding
+                if self.format == Format::BinaryBigEndian {
+                    bytes[..size].reverse();
+                }
+                let [b0, b1, b2, b3, ..] = bytes;
+
+                Ok(match scalar_type {
+                    ScalarType::Int8 => b0 as i8 as f64,
+                    ScalarType::UInt8 => b0 as f64,
+                    ScalarType::Int16 => i16::from_le_bytes([b0, b1]) as f64,
+                    ScalarType::UInt16 => u16::from_le_bytes([b0, b1]) as f64,
+                    ScalarType::Int32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    ScalarType::UInt32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    ScalarType::Float32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    ScalarType::Float64 => f64::from_le_bytes(bytes),
+                })
+            }
+        }
+    }
+
+    /// Returns the next token of an ASCII file, reading new lines as needed.
+    fn next_token(&mut self) -> Result<String, String> {
+        while self.next_token >= self.tokens.len() {
+            let mut line = String::new();
+            let read = self
+                .reader
+                .read_line(&mut line)
+                .map_err(|error| error.to_string())?;
+            if read == 0 {
+                return Err("unexpected end of file".to_string());
+            }
+
+            self.line_number += 1;
+            self.tokens = line.split_whitespace().map(str::to_string).collect();
+            self.next_token = 0;
+        }
+
+        self.next_token += 1;
+        Ok(std::mem::take(&mut self.tokens[self.next_token - 1]))
+    }
+}
+
+/// The attributes of the vertices of a PLY file.
+#[derive(Default)]
+struct VertexData {
+    positions: Vec<Point3<f64>>,
+    normals: Option<Vec<Vector3<f64>>>,
+    uvs: Option<Vec<(f64, f64)>>,
+    colors: Option<Vec<Vector3<f64>>>,
+}
+
+impl VertexData {
+    fn read<R: BufRead>(
+        body: &mut BodyReader<R>,
+        element: &Element,
+        values: &mut Vec<Vec<f64>>,
+    ) -> Result<Self, LoadError> {
+        let position = match (
+            element.scalar(&["x"]),
+            element.scalar(&["y"]),
+            element.scalar(&["z"]),
+        ) {
+            (Some((x, _)), Some((y, _)), Some((z, _))) => [x, y, z],
+            _ => {
+                return Err(LoadError::Invalid(
+                    body.path.to_path_buf(),
+                    "the `vertex` element needs `x`, `y` and `z` properties".to_string(),
+                ))
+            }
+        };
+        let normal = match (
+            element.scalar(&["nx"]),
+            element.scalar(&["ny"]),
+            element.scalar(&["nz"]),
+        ) {
+            (Some((x, _)), Some((y, _)), Some((z, _))) => Some([x, y, z]),
+            _ => None,
+        };
+        let uv = match (
+            element.scalar(&["u", "s", "texture_u", "texture_s"]),
+            element.scalar(&["v", "t", "texture_v", "texture_t"]),
+        ) {
+            (Some((u, _)), Some((v, _))) => Some([u, v]),
+            _ => None,
+        };
+        let color = match (
+            element.scalar(&["red", "r", "diffuse_red"]),
+            element.scalar(&["green", "g", "diffuse_green"]),
+            element.scalar(&["blue", "b", "diffuse_blue"]),
+        ) {
+            (Some((r, scalar_type)), Some((g, _)), Some((b, _))) => {
+                Some(([r, g, b], 1.0 / scalar_type.color_scale()))
+            }
+            _ => None,
+        };
+
+        let mut data = VertexData {
+            positions: vec![],
+            normals: normal.map(|_| vec![]),
+            uvs: uv.map(|_| vec![]),
+            colors: color.map(|_| vec![]),
+        };
+
+        for _ in 0..element.count {
+            body.read_instance(element, values)?;
+            let value = |index: usize| values[index][0];
+
+            data.positions.push(Point3::new(
+                value(position[0]),
+                value(position[1]),
+                value(position[2]),
+            ));
+            if let (Some(normals), Some([x, y, z])) = (&mut data.normals, normal) {
+                normals.push(Vector3::new(value(x), value(y), value(z)));
+            }
+            if let (Some(uvs), Some([u, v])) = (&mut data.uvs, uv) {
+                uvs.push((value(u), value(v)));
+            }
+            if let (Some(colors), Some(([r, g, b], scale))) = (&mut data.colors, color) {
+                colors.push(scale * Vector3::new(value(r), value(g), value(b)));
+            }
+        }
+
+        Ok(data)
+    }
+}
+
+/// Converts a value read from the file to an index, if it is a non-negative integer.
+fn as_index(value: f64) -> Option<usize> {
+    match value >= 0.0 && value.fract() == 0.0 && value <= u32::MAX as f64 {
+        true => Some(value as usize),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Hittable;
+    use crate::loader::temporary_file;
+    use crate::material::Lambertian;
+    use crate::ray::Ray;
+    use crate::world::HitRecord;
+    use real_interval::RealInterval;
+
+    const TRIANGLE_HEADER: &str = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\n\
+        property float y\nproperty float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n";
+
+    fn load_str(name: &str, contents: &[u8]) -> Result<TriangleMesh, LoadError> {
+        load(
+            temporary_file(name, contents),
+            Arc::new(Lambertian::default()),
+        )
+    }
+
+    #[test]
+    fn ascii_triangle() {
+        let file = format!("{TRIANGLE_HEADER}0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n");
+        let mesh = load_str("ascii_triangle.ply", file.as_bytes()).unwrap();
+        assert_eq!(mesh.len(), 1);
+
+        let triangle = &mesh.into_triangles()[0];
+        let ray = Ray::new(
+            Point3::new(0.25, 0.25, 1.0),
+            Vector3::new(0.0, 0.0, -1.0),
+            0.0,
+        );
+        let mut hit_record = HitRecord::default();
+        assert!(triangle.hit(
+            &ray,
+            RealInterval::min_max(1e-3, 10.0),
+            &mut hit_record,
+            &mut rand::thread_rng()
+        ));
+        assert!((hit_record.t - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn quad_is_triangulated() {
+        let file = "ply\nformat ascii 1.0\nelement vertex 4\nproperty float x\nproperty float y\n\
+            property float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n\
+            0 0 0\n1 0 0\n1 1 0\n0 1 0\n4 0 1 2 3\n";
+        let mesh = load_str("quad.ply", file.as_bytes()).unwrap();
+        assert_eq!(mesh.len(), 2);
+    }
+
+    #[test]
+    fn binary_big_endian() {
+        let mut file = TRIANGLE_HEADER
+            .replace("ascii", "binary_big_endian")
+            .into_bytes();
+        for value in [0.0f32, 0.0, 2.0, 1.0, 0.0, 2.0, 0.0, 1.0, 2.0] {
+            file.extend(value.to_be_bytes());
+        }
+        file.push(3);
+        for index in [0i32, 1, 2] {
+            file.extend(index.to_be_bytes());
+        }
+
+        let mesh = load_str("big_endian.ply", &file).unwrap();
+        assert_eq!(mesh.len(), 1);
+
+        let triangle = &mesh.into_triangles()[0];
+        let ray = Ray::new(
+            Point3::new(0.25, 0.25, 0.0),
+            Vector3::new(0.0, 0.0, 1.0),
+            0.0,
+        );
+        let mut hit_record = HitRecord::default();
+        assert!(triangle.hit(
+            &ray,
+            RealInterval::min_max(1e-3, 10.0),
+            &mut hit_record,
+            &mut rand::thread_rng()
+        ));
+        assert!((hit_record.t - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn impossible_count() {
+        let file = TRIANGLE_HEADER.replace("face 1", "face 18446744073709551615")
+            + "0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n";
+        assert!(matches!(
+            load_str("impossible_count.ply", file.as_bytes()),
+            Err(LoadError::Invalid(..))
+        ));
+    }
+
+    #[test]
+    fn invalid_indices() {
+        for (name, face) in [
+            ("negative_index.ply", "3 0 -1 2"),
+            ("fractional_index.ply", "3 0 1.5 2"),
+            ("out_of_range_index.ply", "3 0 1 3"),
+        ] {
+            let file = format!("{TRIANGLE_HEADER}0 0 0\n1 0 0\n0 1 0\n{face}\n");
+            assert!(matches!(
+                load_str(name, file.as_bytes()),
+                Err(LoadError::Parse { line: 13, .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn invalid_list_length() {
+        let file = TRIANGLE_HEADER.replace("list uchar int", "list float int")
+            + "0 0 0\n1 0 0\n0 1 0\n-3 0 1 2\n";
+        assert!(matches!(
+            load_str("negative_length.ply", file.as_bytes()),
+            Err(LoadError::Parse { line: 13, .. })
+        ));
+    }
+}
@@ -0,0 +1,215 @@
+//! Loaders for voxel grids, used as the density of a `HeterogeneousMedium`:
+//! Mitsuba `.vol` files, and raw arrays of values without header.
+
+use nalgebra::Point3;
+use std::path::Path;
+
+use super::LoadError;
+use crate::volume::VoxelGrid;
+
+/// The type of the values stored in a raw grid file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawType {
+    /// Unsigned bytes, mapped to `[0, 1]`.
+    U8,
+    /// Little endian 32 bits floats.
+    F32,
+}
+
+/// Loads a Mitsuba `.vol` grid, with 32 bits float values. Only the first channel of each voxel is used.
+/// The grid fills the bounding box stored in the file.
+pub fn load(path: impl AsRef<Path>) -> Result<VoxelGrid, LoadError> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path).map_err(|error| LoadError::Io(path.to_path_buf(), error))?;
+    let invalid = |message: String| LoadError::Invalid(path.to_path_buf(), message);
+
+    // The header: "VOL", the version, the encoding, the resolution, the channels and the bounding box.
+    const HEADER_SIZE: usize = 48;
+    if bytes.len() < HEADER_SIZE || &bytes[0..3] != b"VOL" {
+        return Err(invalid("not a `.vol` file".to_string()));
+    }
+    if bytes[3] != 3 {
+        return Err(invalid(format!("unsupported version {}", bytes[3])));
+    }
+
+    let int = |offset: usize| i32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+    let float = |offset: usize| f32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+
+    let encoding = int(4);
+    if encoding != 1 {
+        return Err(invalid(format!(
+            "unsupported encoding {encoding}, only 32 bits floats (1) are supported"
+        )));
+    }
+
+    let dimensions = [int(8), int(12), int(16), int(20)];
+    if dimensions.iter().any(|&n| n <= 0) {
+        return Err(invalid(format!("invalid dimensions {dimensions:?}")));
+    }
+    let [nx, ny, nz, channels] = dimensions.map(|n| n as usize);
+
+    let min = Point3::new(float(24), float(28), float(32)).cast::<f64>();
+    let max = Point3::new(float(36), float(40), float(44)).cast::<f64>();
+
+    let count = nx.checked_mul(ny).and_then(|n| n.checked_mul(nz));
+    let Some((count, value_count)) =
+        count.and_then(|count| Some((count, count.checked_mul(channels)?)))
+    else {
+        return Err(invalid(format!("dimensions {dimensions:?} are too large")));
+    };
+    let data = &bytes[HEADER_SIZE..];
+    if data.len() / 4 < value_count {
+        return Err(invalid(format!(
+            "expected {value_count} values, but the file contains only {}",
+            data.len() / 4
+        )));
+    }
+
+    let values = (0..count)
+        .map(|voxel| {
+            let offset = voxel * channels * 4;
+            f32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+        })
+        .collect();
+
+    Ok(VoxelGrid::new([nx, ny, nz], values, min, max))
+}
+
+/// Loads a raw grid, without header, of the given resolution. The values are ordered with `x` varying first,
+/// then `y`, then `z`. The grid fills the box between two opposite corners.
+pub fn load_raw(
+    path: impl AsRef<Path>,
+    resolution: [usize; 3],
+    value_type: RawType,
+    a: Point3<f64>,
+    b: Point3<f64>,
+) -> Result<VoxelGrid, LoadError> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path).map_err(|error| LoadError::Io(path.to_path_buf(), error))?;
+
+    let value_size = match value_type {
+        RawType::U8 => 1,
+        RawType::F32 => 4,
+    };
+    let size = resolution
+        .iter()
+        .try_fold(value_size, |size: usize, &n| size.checked_mul(n));
+    if !matches!(size, Some(size) if size != 0 && bytes.len() == size) {
+        return Err(LoadError::Invalid(
+            path.to_path_buf(),
+            format!(
+                "a {}x{}x{} grid needs {} bytes, but the file contains {}",
+                resolution[0],
+                resolution[1],
+                resolution[2],
+                size.map_or("too many".to_string(), |size| size.to_string()),
+                bytes.len()
+            ),
+        ));
+    }
+
+    let values = match value_type {
+        RawType::U8 => bytes.iter().map(|&value| value as f32 / 255.0).collect(),
+        RawType::F32 => bytes
+            .chunks_exact(4)
+            .map(|value| f32::from_le_bytes(value.try_into().unwrap()))
+            .collect(),
+    };
+
+    Ok(VoxelGrid::new(resolution, values, a, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::temporary_file;
+    use crate::volume::Density;
+
+    /// A `.vol` file with the given header fields, filling the box from the origin to (2, 1, 1).
+    fn vol_file(version: u8, dimensions: [i32; 4], values: &[f32]) -> Vec<u8> {
+        let mut file = b"VOL".to_vec();
+        file.push(version);
+        file.extend(1i32.to_le_bytes());
+        for n in dimensions {
+            file.extend(n.to_le_bytes());
+        }
+        for bound in [0.0f32, 0.0, 0.0, 2.0, 1.0, 1.0] {
+            file.extend(bound.to_le_bytes());
+        }
+        for value in values {
+            file.extend(value.to_le_bytes());
+        }
+        file
+    }
+
+    #[test]
+    fn vol_grid() {
+        // Two voxels of two channels, the second channel is ignored.
+        let file = vol_file(3, [2, 1, 1, 2], &[0.25, 9.0, 0.75, 9.0]);
+        let grid = load(temporary_file("grid.vol", &file)).unwrap();
+
+        assert_eq!(
+            grid.bounds(),
+            (Point3::origin(), Point3::new(2.0, 1.0, 1.0))
+        );
+        assert_eq!(grid.density(&Point3::new(0.5, 0.5, 0.5)), 0.25);
+        assert_eq!(grid.density(&Point3::new(1.5, 0.5, 0.5)), 0.75);
+        assert_eq!(grid.majorant(), 0.75);
+    }
+
+    #[test]
+    fn vol_invalid_header() {
+        let mut wrong_magic = vol_file(3, [1, 1, 1, 1], &[1.0]);
+        wrong_magic[0] = b'X';
+        let short = &vol_file(3, [1, 1, 1, 1], &[])[..40];
+
+        for (name, file) in [
+            ("wrong_magic.vol", wrong_magic),
+            ("short.vol", short.to_vec()),
+            ("version.vol", vol_file(2, [1, 1, 1, 1], &[1.0])),
+            ("empty_dimension.vol", vol_file(3, [1, 0, 1, 1], &[])),
+            ("negative_dimension.vol", vol_file(3, [1, 1, -1, 1], &[1.0])),
+        ] {
+            assert!(
+                matches!(
+                    load(temporary_file(name, &file)),
+                    Err(LoadError::Invalid(..))
+                ),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn vol_invalid_size() {
+        let overflow = vol_file(3, [i32::MAX; 4], &[1.0]);
+        let truncated = vol_file(3, [2, 2, 1, 1], &[1.0, 2.0, 3.0]);
+
+        for (name, file) in [("overflow.vol", overflow), ("truncated.vol", truncated)] {
+            assert!(
+                matches!(
+                    load(temporary_file(name, &file)),
+                    Err(LoadError::Invalid(..))
+                ),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn raw_grid() {
+        let (a, b) = (Point3::origin(), Point3::new(1.0, 1.0, 1.0));
+        let path = temporary_file("grid.raw", &[0, 255]);
+
+        let grid = load_raw(&path, [2, 1, 1], RawType::U8, a, b).unwrap();
+        assert_eq!(grid.majorant(), 1.0);
+
+        for resolution in [[1, 1, 1], [2, 2, 1], [2, 0, 1], [usize::MAX, 2, 1]] {
+            assert!(matches!(
+                load_raw(&path, resolution, RawType::U8, a, b),
+                Err(LoadError::Invalid(..))
+            ));
+        }
+        assert!(load_raw(&path, [1, 1, 1], RawType::F32, a, b).is_err());
+    }
+}
@@ -1,25 +1,106 @@
-use nalgebra::Vector3;
+use image::ImageResult;
+use nalgebra::{Point3, Vector3};
 use rand::{Rng, RngCore};
+use std::path::Path;
+use std::sync::Arc;
 
+use crate::microfacet::{fresnel_conductor, fresnel_dielectric, Ggx};
 use crate::ray::Ray;
-use crate::texture::Texture;
+use crate::texture::{ImageTexture, Texture};
 use crate::utility::*;
 use crate::world::HitRecord;
 
-// TODO: transform Material into a trait
-#[derive(Clone, Copy)]
-pub enum Material {
-    Lambertian(Vector3<f64>),
-    TexturedLambertian(Texture),
-    Hemisphere(Vector3<f64>),
-    Metal(Vector3<f64>, f64),
-    Dielectric(f64),
-}
-
-impl Material {
+/// The way the surface of an object interacts with light.
+/// Implement this trait to add custom materials to a scene.
+pub trait Material: Send + Sync {
     /// If the material is diffusive, returns `true` and modifies `scattered_ray` and `attenuation`.
     /// If the material isn't diffusive, returns `false`.
-    pub fn scatter(
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        attenuation: &mut Vector3<f64>,
+        scattered_ray: &mut Ray,
+        rng: &mut dyn RngCore,
+    ) -> bool;
+
+    /// The light emitted by the material at the hit point. Most materials do not emit light.
+    fn emitted(&self, _ray_in: &Ray, _hit_record: &HitRecord) -> Vector3<f64> {
+        Vector3::zeros()
+    }
+
+    /// The probability density, with respect to solid angle, that `scatter` generates `scattered_ray`.
+    /// Materials scattering in a single direction, such as mirrors, return `0.0`.
+    fn pdf(&self, _ray_in: &Ray, _hit_record: &HitRecord, _scattered_ray: &Ray) -> f64 {
+        0.0
+    }
+
+    /// The light scattered towards `ray_in` for light coming from the direction of `scattered_ray`,
+    /// that is the BSDF multiplied by the cosine term.
+    /// Must be consistent with `scatter` and `pdf`: the attenuation of a scattered ray is `eval / pdf`.
+    /// Used to sample the lights directly; materials returning `0.0` in `pdf` do not need to implement it.
+    fn eval(&self, _ray_in: &Ray, _hit_record: &HitRecord, _scattered_ray: &Ray) -> Vector3<f64> {
+        Vector3::zeros()
+    }
+}
+
+/// A diffuse material, scattering rays following a cosine distribution around the normal.
+pub struct Lambertian {
+    albedo: Vector3<f64>,
+}
+
+impl Lambertian {
+    pub fn new(albedo: Vector3<f64>) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Default for Lambertian {
+    fn default() -> Self {
+        Self::new(Vector3::new(0.5, 0.5, 0.5))
+    }
+}
+
+impl Material for Lambertian {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        attenuation: &mut Vector3<f64>,
+        scattered_ray: &mut Ray,
+        rng: &mut dyn RngCore,
+    ) -> bool {
+        *scattered_ray = Ray::new(
+            hit_record.hit_point,
+            lambertian_direction(hit_record, rng),
+            ray_in.time(),
+        );
+        *attenuation = self.albedo;
+        true
+    }
+
+    fn pdf(&self, _ray_in: &Ray, hit_record: &HitRecord, scattered_ray: &Ray) -> f64 {
+        lambertian_pdf(hit_record, scattered_ray)
+    }
+
+    fn eval(&self, _ray_in: &Ray, hit_record: &HitRecord, scattered_ray: &Ray) -> Vector3<f64> {
+        lambertian_pdf(hit_record, scattered_ray) * self.albedo
+    }
+}
+
+/// A diffuse material whose color is given by a `Texture`.
+pub struct TexturedLambertian {
+    texture: Texture,
+}
+
+impl TexturedLambertian {
+    pub fn new(texture: Texture) -> Self {
+        Self { texture }
+    }
+}
+
+impl Material for TexturedLambertian {
+    fn scatter(
         &self,
         ray_in: &Ray,
         hit_record: &HitRecord,
@@ -27,76 +108,1322 @@ impl Material {
         scattered_ray: &mut Ray,
         rng: &mut dyn RngCore,
     ) -> bool {
-        use Material::*;
-        match *self {
-            Lambertian(albedo) => {
-                let mut scatter_direction = hit_record.normal + random_unit_vector(rng);
-
-                // Catch degenerate scatter direction
-                if scatter_direction.norm_squared() < 1e-8 {
-                    scatter_direction = hit_record.normal;
+        *scattered_ray = Ray::new(
+            hit_record.hit_point,
+            lambertian_direction(hit_record, rng),
+            ray_in.time(),
+        );
+        *attenuation = self.texture.sample(hit_record);
+        true
+    }
+
+    fn pdf(&self, _ray_in: &Ray, hit_record: &HitRecord, scattered_ray: &Ray) -> f64 {
+        lambertian_pdf(hit_record, scattered_ray)
+    }
+
+    fn eval(&self, _ray_in: &Ray, hit_record: &HitRecord, scattered_ray: &Ray) -> Vector3<f64> {
+        lambertian_pdf(hit_record, scattered_ray) * self.texture.sample(hit_record)
+    }
+}
+
+/// A diffuse material using the colors of the vertices of a mesh.
+/// The `albedo` is used for surfaces without vertex colors.
+pub struct VertexColor {
+    albedo: Vector3<f64>,
+}
+
+impl VertexColor {
+    pub fn new(albedo: Vector3<f64>) -> Self {
+        Self { albedo }
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Vector3<f64> {
+        hit_record.vertex_color.unwrap_or(self.albedo)
+    }
+}
+
+impl Default for VertexColor {
+    fn default() -> Self {
+        Self::new(Vector3::new(0.5, 0.5, 0.5))
+    }
+}
+
+impl Material for VertexColor {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        attenuation: &mut Vector3<f64>,
+        scattered_ray: &mut Ray,
+        rng: &mut dyn RngCore,
+    ) -> bool {
+        *scattered_ray = Ray::new(
+            hit_record.hit_point,
+            lambertian_direction(hit_record, rng),
+            ray_in.time(),
+        );
+        *attenuation = self.albedo(hit_record);
+        true
+    }
+
+    fn pdf(&self, _ray_in: &Ray, hit_record: &HitRecord, scattered_ray: &Ray) -> f64 {
+        lambertian_pdf(hit_record, scattered_ray)
+    }
+
+    fn eval(&self, _ray_in: &Ray, hit_record: &HitRecord, scattered_ray: &Ray) -> Vector3<f64> {
+        lambertian_pdf(hit_record, scattered_ray) * self.albedo(hit_record)
+    }
+}
+
+/// A diffuse material, scattering rays uniformly on the hemisphere around the normal.
+pub struct Hemisphere {
+    albedo: Vector3<f64>,
+}
+
+impl Hemisphere {
+    pub fn new(albedo: Vector3<f64>) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Material for Hemisphere {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        attenuation: &mut Vector3<f64>,
+        scattered_ray: &mut Ray,
+        rng: &mut dyn RngCore,
+    ) -> bool {
+        let direction = random_on_hemisphere(&hit_record.normal, rng);
+        *scattered_ray = Ray::new(hit_record.hit_point, direction, ray_in.time());
+        *attenuation = self.albedo;
+        true
+    }
+
+    fn pdf(&self, _ray_in: &Ray, hit_record: &HitRecord, scattered_ray: &Ray) -> f64 {
+        match scattered_ray.direction().dot(&hit_record.normal) > 0.0 {
+            true => 1.0 / (2.0 * std::f64::consts::PI),
+            false => 0.0,
+        }
+    }
+
+    fn eval(&self, ray_in: &Ray, hit_record: &HitRecord, scattered_ray: &Ray) -> Vector3<f64> {
+        self.pdf(ray_in, hit_record, scattered_ray) * self.albedo
+    }
+}
+
+/// A reflective material. The `fuzz` parameter randomly perturbs the reflected rays.
+pub struct Metal {
+    albedo: Vector3<f64>,
+    fuzz: f64,
+}
+
+impl Metal {
+    pub fn new(albedo: Vector3<f64>, fuzz: f64) -> Self {
+        Self { albedo, fuzz }
+    }
+}
+
+impl Material for Metal {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        attenuation: &mut Vector3<f64>,
+        scattered_ray: &mut Ray,
+        rng: &mut dyn RngCore,
+    ) -> bool {
+        let direction =
+            reflect(ray_in.direction(), &hit_record.normal) + self.fuzz * random_unit_vector(rng);
+        *scattered_ray = Ray::new(hit_record.hit_point, direction, ray_in.time());
+        *attenuation = self.albedo;
+
+        // If the scattered ray is below the surface, absorb it (return false)
+        direction.dot(&hit_record.normal) > 0.0
+    }
+}
+
+/// A metal, reflecting light on a rough surface described by a GGX microfacet distribution.
+/// The color comes from the complex index of refraction `eta + i k`, given for the red, green and blue channels.
+pub struct Conductor {
+    eta: Vector3<f64>,
+    k: Vector3<f64>,
+    distribution: Ggx,
+}
+
+impl Conductor {
+    pub fn new(eta: Vector3<f64>, k: Vector3<f64>, distribution: Ggx) -> Self {
+        Self {
+            eta,
+            k,
+            distribution,
+        }
+    }
+
+    pub fn gold(distribution: Ggx) -> Self {
+        Self::new(
+            Vector3::new(0.143, 0.374, 1.442),
+            Vector3::new(3.983, 2.385, 1.603),
+            distribution,
+        )
+    }
+
+    pub fn copper(distribution: Ggx) -> Self {
+        Self::new(
+            Vector3::new(0.200, 0.924, 1.102),
+            Vector3::new(3.912, 2.452, 2.142),
+            distribution,
+        )
+    }
+
+    pub fn aluminium(distribution: Ggx) -> Self {
+        Self::new(
+            Vector3::new(1.657, 0.880, 0.521),
+            Vector3::new(9.224, 6.270, 4.837),
+            distribution,
+        )
+    }
+
+    pub fn silver(distribution: Ggx) -> Self {
+        Self::new(
+            Vector3::new(0.155, 0.117, 0.138),
+            Vector3::new(4.828, 3.122, 2.147),
+            distribution,
+        )
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        attenuation: &mut Vector3<f64>,
+        scattered_ray: &mut Ray,
+        rng: &mut dyn RngCore,
+    ) -> bool {
+        let basis = hit_record.shading_basis();
+        let wo = basis.to_local(&-ray_in.direction().normalize());
+        if wo.z <= 0.0 {
+            return false;
+        }
+
+        let (wi, weight) = match self.distribution.is_smooth() {
+            true => (
+                Vector3::new(-wo.x, -wo.y, wo.z),
+                fresnel_conductor(wo.z, &self.eta, &self.k),
+            ),
+            false => {
+                let m = self.distribution.sample_visible_normal(&wo, rng);
+                let wi = reflect(&-wo, &m);
+                if wi.z <= 0.0 {
+                    return false;
                 }
+                // The density of the visible normals cancels out with most of the BSDF.
+                let weight = self.distribution.g(&wo, &wi) / self.distribution.g1(&wo);
+                (
+                    wi,
+                    fresnel_conductor(wo.dot(&m), &self.eta, &self.k) * weight,
+                )
+            }
+        };
+
+        *attenuation = weight;
+        *scattered_ray = Ray::new(hit_record.hit_point, basis.to_world(&wi), ray_in.time());
+        true
+    }
+
+    fn pdf(&self, ray_in: &Ray, hit_record: &HitRecord, scattered_ray: &Ray) -> f64 {
+        if self.distribution.is_smooth() {
+            return 0.0;
+        }
+
+        let Some((wo, _, m)) = reflection_directions(ray_in, hit_record, scattered_ray) else {
+            return 0.0;
+        };
+        self.distribution.visible_normal_pdf(&wo, &m) / (4.0 * wo.dot(&m))
+    }
+
+    fn eval(&self, ray_in: &Ray, hit_record: &HitRecord, scattered_ray: &Ray) -> Vector3<f64> {
+        if self.distribution.is_smooth() {
+            return Vector3::zeros();
+        }
 
-                *scattered_ray = Ray::new(hit_record.hit_point, scatter_direction, ray_in.time());
-                *attenuation = albedo;
-                true
+        let Some((wo, wi, m)) = reflection_directions(ray_in, hit_record, scattered_ray) else {
+            return Vector3::zeros();
+        };
+        // `D G F / (4 cos_o cos_i)`, multiplied by `cos_i`.
+        fresnel_conductor(wo.dot(&m), &self.eta, &self.k)
+            * self.distribution.d(&m)
+            * self.distribution.g(&wo, &wi)
+            / (4.0 * wo.z)
+    }
+}
+
+/// A transparent material, such as glass or water, characterised by its refraction index.
+/// The light travelling inside may be absorbed following the Beer-Lambert law, to model colored glass or liquids.
+pub struct Dielectric {
+    index: f64,
+    /// The fraction of light absorbed per unit of distance inside the material, for each color channel.
+    /// The distance travelled inside is the length of the ray hitting the back face, which assumes that the ray
+    /// started on the surface of the object: a ray scattered by a medium inside the object, or leaving
+    /// another object inside it, is only attenuated over its own length.
+    absorption: Vector3<f64>,
+}
+
+impl Dielectric {
+    /// Creates a perfectly clear material.
+    pub fn new(index: f64) -> Self {
+        Self::with_absorption(index, Vector3::zeros())
+    }
+
+    /// Creates a material absorbing light inside: after a distance `d`, the light is attenuated
+    /// by `exp(-absorption * d)`. Thin parts of the object are lightly tinted, while thick parts are darker.
+    pub fn with_absorption(index: f64, absorption: Vector3<f64>) -> Self {
+        assert!(absorption.iter().all(|&a| a >= 0.0));
+
+        Self { index, absorption }
+    }
+}
+
+impl Material for Dielectric {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        attenuation: &mut Vector3<f64>,
+        scattered_ray: &mut Ray,
+        rng: &mut dyn RngCore,
+    ) -> bool {
+        // Hitting the back face means the ray travelled inside the material since its origin.
+        *attenuation = match hit_record.front_face {
+            true => Vector3::new(1.0, 1.0, 1.0),
+            false => {
+                let distance = hit_record.t * ray_in.direction().norm();
+                (-distance * self.absorption).map(f64::exp)
             }
-            TexturedLambertian(texture) => {
-                let mut scatter_direction = hit_record.normal + random_unit_vector(rng);
+        };
+        let refraction_ratio = match hit_record.front_face {
+            true => 1.0 / self.index, // ray goes from air to the dielectric
+            false => self.index,      // ray goes from the dielectric to the air
+        };
+
+        let unit_direction = ray_in.direction().normalize();
+
+        let cos_theta = -unit_direction.dot(&hit_record.normal);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+        // If sin(theta) is too big, there's total reflexion
+        let cannot_refract = refraction_ratio * sin_theta > 1.0;
+        let direction = match cannot_refract || reflectance(cos_theta, refraction_ratio) > rng.gen()
+        {
+            true => reflect(&unit_direction, &hit_record.normal),
+            false => refract(&unit_direction, &hit_record.normal, refraction_ratio),
+        };
+
+        *scattered_ray = Ray::new(hit_record.hit_point, direction, ray_in.time());
+        true
+    }
+}
+
+/// A transparent material with a rough surface, such as frosted glass, described by a GGX microfacet distribution,
+/// following Walter et al., "Microfacet Models for Refraction through Rough Surfaces" (2007).
+/// The light is reflected or refracted by each microfacet according to the exact Fresnel equations.
+pub struct RoughDielectric {
+    index: f64,
+    distribution: Ggx,
+}
+
+impl RoughDielectric {
+    pub fn new(index: f64, distribution: Ggx) -> Self {
+        assert!(index > 0.0);
+
+        Self {
+            index,
+            distribution,
+        }
+    }
+
+    /// The ratio of the index of the side opposite to the ray to the index of the side of the ray.
+    fn eta(&self, hit_record: &HitRecord) -> f64 {
+        match hit_record.front_face {
+            true => self.index,
+            false => 1.0 / self.index,
+        }
+    }
 
-                // Catch degenerate scatter direction
-                if scatter_direction.norm_squared() < 1e-8 {
-                    scatter_direction = hit_record.normal;
+    /// The outgoing and incident directions in the shading frame, with the microfacet normal between them,
+    /// if a microfacet can scatter the light between these directions.
+    fn directions(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        scattered_ray: &Ray,
+    ) -> Option<(Vector3<f64>, Vector3<f64>, Vector3<f64>)> {
+        let basis = hit_record.shading_basis();
+        let wo = basis.to_local(&-ray_in.direction().normalize());
+        let wi = basis.to_local(&scattered_ray.direction().normalize());
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return None;
+        }
+
+        let m = match wi.z > 0.0 {
+            true => wo + wi,
+            false => -(wo + self.eta(hit_record) * wi),
+        }
+        .try_normalize(1e-12)?;
+        let m = match m.z < 0.0 {
+            true => -m,
+            false => m,
+        };
+
+        // The microfacet must face the ray, and the incident direction must be on the side given by the event.
+        match wo.dot(&m) > 0.0 && (wi.dot(&m) > 0.0) == (wi.z > 0.0) {
+            true => Some((wo, wi, m)),
+            false => None,
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        attenuation: &mut Vector3<f64>,
+        scattered_ray: &mut Ray,
+        rng: &mut dyn RngCore,
+    ) -> bool {
+        let basis = hit_record.shading_basis();
+        let wo = basis.to_local(&-ray_in.direction().normalize());
+        if wo.z <= 0.0 {
+            return false;
+        }
+
+        let eta = self.eta(hit_record);
+        let m = match self.distribution.is_smooth() {
+            true => Vector3::new(0.0, 0.0, 1.0),
+            false => self.distribution.sample_visible_normal(&wo, rng),
+        };
+
+        // The reflectance is used as the probability of reflecting, and cancels out in the attenuation.
+        let cos_theta = wo.dot(&m);
+        let wi = match fresnel_dielectric(cos_theta, eta) > rng.gen() {
+            true => reflect(&-wo, &m),
+            false => refract(&-wo, &m, 1.0 / eta),
+        };
+
+        *attenuation = match self.distribution.is_smooth() {
+            true => Vector3::new(1.0, 1.0, 1.0),
+            false => {
+                // The scattered direction went on the wrong side of the macro surface.
+                if (wi.z > 0.0) != (wi.dot(&m) > 0.0) {
+                    return false;
                 }
+                self.distribution.g(&wo, &wi) / self.distribution.g1(&wo)
+                    * Vector3::new(1.0, 1.0, 1.0)
+            }
+        };
+        *scattered_ray = Ray::new(hit_record.hit_point, basis.to_world(&wi), ray_in.time());
+        true
+    }
 
-                *scattered_ray = Ray::new(hit_record.hit_point, scatter_direction, ray_in.time());
-                *attenuation = texture.value(hit_record.u, hit_record.v, hit_record.hit_point);
-                true
+    fn pdf(&self, ray_in: &Ray, hit_record: &HitRecord, scattered_ray: &Ray) -> f64 {
+        if self.distribution.is_smooth() {
+            return 0.0;
+        }
+
+        let Some((wo, wi, m)) = self.directions(ray_in, hit_record, scattered_ray) else {
+            return 0.0;
+        };
+        let eta = self.eta(hit_record);
+        let fresnel = fresnel_dielectric(wo.dot(&m), eta);
+        let normal_pdf = self.distribution.visible_normal_pdf(&wo, &m);
+
+        match wi.z > 0.0 {
+            true => fresnel * normal_pdf / (4.0 * wo.dot(&m)),
+            false => {
+                let denominator = wo.dot(&m) + eta * wi.dot(&m);
+                (1.0 - fresnel) * normal_pdf * eta * eta * wi.dot(&m).abs()
+                    / (denominator * denominator)
             }
-            Hemisphere(albedo) => {
-                let direction = random_on_hemisphere(&hit_record.normal, rng);
-                *scattered_ray = Ray::new(hit_record.hit_point, direction, ray_in.time());
-                *attenuation = albedo;
-                true
+        }
+    }
+
+    fn eval(&self, ray_in: &Ray, hit_record: &HitRecord, scattered_ray: &Ray) -> Vector3<f64> {
+        if self.distribution.is_smooth() {
+            return Vector3::zeros();
+        }
+
+        let Some((wo, wi, m)) = self.directions(ray_in, hit_record, scattered_ray) else {
+            return Vector3::zeros();
+        };
+        let eta = self.eta(hit_record);
+        let fresnel = fresnel_dielectric(wo.dot(&m), eta);
+        let d = self.distribution.d(&m);
+        let g = self.distribution.g(&wo, &wi);
+
+        // The BSDF multiplied by `|cos_i|`.
+        let value = match wi.z > 0.0 {
+            true => fresnel * d * g / (4.0 * wo.z),
+            false => {
+                let denominator = wo.dot(&m) + eta * wi.dot(&m);
+                (1.0 - fresnel) * d * g * eta * eta * wo.dot(&m) * wi.dot(&m).abs()
+                    / (wo.z * denominator * denominator)
             }
-            Metal(albedo, fuzz) => {
-                let direction = reflect(ray_in.direction(), &hit_record.normal)
-                    + fuzz * random_unit_vector(rng);
-                *scattered_ray = Ray::new(hit_record.hit_point, direction, ray_in.time());
-                *attenuation = albedo;
-
-                // If the scattered ray is below the surface, absorb it (return false)
-                direction.dot(&hit_record.normal) > 0.0
+        };
+        value * Vector3::new(1.0, 1.0, 1.0)
+    }
+}
+
+/// The smallest roughness of the specular lobes of `Principled`, so that they can always be sampled as glossy lobes.
+const PRINCIPLED_MIN_ROUGHNESS: f64 = 0.05;
+
+/// The roughness of the clear coat layer of `Principled`.
+const CLEARCOAT_ROUGHNESS: f64 = 0.2;
+
+/// A physically based material following the "principled" model of Burley, "Physically Based Shading at Disney"
+/// (2012), extended with transmission. It covers most real surfaces with a few intuitive parameters, such as those
+/// used by content creation tools.
+/// Every parameter is given by a texture. The parameters other than the base color are in `[0, 1]`, and are
+/// read from the mean of the color channels.
+pub struct Principled {
+    base_color: Texture,
+    /// Blends between a dielectric (`0`) and a metal (`1`), whose reflections are tinted by the base color.
+    metallic: Texture,
+    roughness: Texture,
+    /// The strength of the reflections of the dielectric part, `0.5` matching an index of refraction of 1.5.
+    specular: Texture,
+    /// A soft reflection at grazing angles, for cloth.
+    sheen: Texture,
+    /// The strength of a second, colorless and glossy specular layer, such as varnish.
+    clearcoat: Texture,
+    /// The fraction of the dielectric part letting light through, tinted by the base color, such as colored glass.
+    transmission: Texture,
+    /// The index of refraction used for the transmission.
+    index: f64,
+}
+
+/// The parameters of a `Principled` material at a hit point.
+struct PrincipledParameters {
+    base_color: Vector3<f64>,
+    metallic: f64,
+    roughness: f64,
+    specular: f64,
+    sheen: f64,
+    clearcoat: f64,
+    transmission: f64,
+}
+
+impl PrincipledParameters {
+    /// The weights of the diffuse, specular, clear coat and transmission lobes, used to choose the lobe to sample.
+    fn lobe_weights(&self) -> [f64; 4] {
+        let dielectric = 1.0 - self.metallic;
+        [
+            dielectric * (1.0 - self.transmission),
+            1.0 - dielectric * self.transmission,
+            0.25 * self.clearcoat,
+            dielectric * self.transmission,
+        ]
+    }
+
+    fn specular_distribution(&self) -> Ggx {
+        Ggx::from_roughness(self.roughness.max(PRINCIPLED_MIN_ROUGHNESS), 0.0)
+    }
+
+    /// The reflectance at normal incidence of the specular lobe.
+    fn specular_color(&self) -> Vector3<f64> {
+        Vector3::repeat(0.08 * self.specular).lerp(&self.base_color, self.metallic)
+    }
+}
+
+impl Principled {
+    /// Creates a rough dielectric material of the given color, the other parameters having their default values:
+    /// a roughness and a specular of `0.5`, and no metal, sheen, clear coat or transmission.
+    pub fn new(base_color: Texture) -> Self {
+        let constant = |value: f64| Texture::SolidColor(Vector3::repeat(value));
+
+        Self {
+            base_color,
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            sheen: constant(0.0),
+            clearcoat: constant(0.0),
+            transmission: constant(0.0),
+            index: 1.5,
+        }
+    }
+
+    pub fn set_metallic(&mut self, metallic: Texture) {
+        self.metallic = metallic;
+    }
+
+    pub fn set_roughness(&mut self, roughness: Texture) {
+        self.roughness = roughness;
+    }
+
+    pub fn set_specular(&mut self, specular: Texture) {
+        self.specular = specular;
+    }
+
+    pub fn set_sheen(&mut self, sheen: Texture) {
+        self.sheen = sheen;
+    }
+
+    pub fn set_clearcoat(&mut self, clearcoat: Texture) {
+        self.clearcoat = clearcoat;
+    }
+
+    pub fn set_transmission(&mut self, transmission: Texture) {
+        self.transmission = transmission;
+    }
+
+    /// Sets the index of refraction of the transmission, `1.5` by default.
+    pub fn set_index(&mut self, index: f64) {
+        assert!(index > 0.0);
+        self.index = index;
+    }
+
+    fn parameters(&self, hit_record: &HitRecord) -> PrincipledParameters {
+        let scalar = |texture: &Texture| texture.sample(hit_record).mean().clamp(0.0, 1.0);
+
+        PrincipledParameters {
+            base_color: self.base_color.sample(hit_record),
+            metallic: scalar(&self.metallic),
+            roughness: scalar(&self.roughness),
+            specular: scalar(&self.specular),
+            sheen: scalar(&self.sheen),
+            clearcoat: scalar(&self.clearcoat),
+            transmission: scalar(&self.transmission),
+        }
+    }
+
+    fn transmission(&self, parameters: &PrincipledParameters) -> RoughDielectric {
+        RoughDielectric::new(self.index, parameters.specular_distribution())
+    }
+
+    fn clearcoat_distribution() -> Ggx {
+        Ggx::from_roughness(CLEARCOAT_ROUGHNESS, 0.0)
+    }
+
+    /// The sum of the lobes, multiplied by the cosine term.
+    fn eval_lobes(
+        &self,
+        parameters: &PrincipledParameters,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        scattered_ray: &Ray,
+    ) -> Vector3<f64> {
+        let [diffuse_weight, specular_weight, clearcoat_weight, transmission_weight] =
+            parameters.lobe_weights();
+        let mut value = Vector3::zeros();
+
+        if let Some((wo, wi, m)) = reflection_directions(ray_in, hit_record, scattered_ray) {
+            let cos_d = wi.dot(&m);
+
+            // Diffuse, with a retro-reflection at grazing angles on rough surfaces, and the sheen.
+            let fd90 = 0.5 + 2.0 * parameters.roughness * cos_d * cos_d;
+            let diffuse = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z))
+                * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z))
+                / std::f64::consts::PI;
+            let sheen = parameters.sheen * schlick_weight(cos_d);
+            value +=
+                diffuse_weight * (diffuse * parameters.base_color + Vector3::repeat(sheen)) * wi.z;
+
+            let specular = parameters.specular_distribution();
+            let f0 = parameters.specular_color();
+            let fresnel = f0 + (Vector3::repeat(1.0) - f0) * schlick_weight(wo.dot(&m));
+            value +=
+                specular_weight * fresnel * specular.d(&m) * specular.g(&wo, &wi) / (4.0 * wo.z);
+
+            let clearcoat = Self::clearcoat_distribution();
+            let fresnel = 0.04 + 0.96 * schlick_weight(wo.dot(&m));
+            value += Vector3::repeat(
+                clearcoat_weight * fresnel * clearcoat.d(&m) * clearcoat.g(&wo, &wi) / (4.0 * wo.z),
+            );
+        }
+
+        if transmission_weight > 0.0 {
+            let transmission =
+                self.transmission(parameters)
+                    .eval(ray_in, hit_record, scattered_ray);
+            // Only the light going through the material is tinted.
+            let tint = match scattered_ray.direction().dot(&hit_record.normal) < 0.0 {
+                true => parameters.base_color,
+                false => Vector3::repeat(1.0),
+            };
+            value += transmission_weight * transmission.component_mul(&tint);
+        }
+
+        value
+    }
+
+    /// The probability density of sampling the scattered ray, choosing one of the lobes according to their weights.
+    fn pdf_lobes(
+        &self,
+        parameters: &PrincipledParameters,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        scattered_ray: &Ray,
+    ) -> f64 {
+        let weights = parameters.lobe_weights();
+        let [diffuse_weight, specular_weight, clearcoat_weight, transmission_weight] = weights;
+        let mut pdf = 0.0;
+
+        if let Some((wo, wi, m)) = reflection_directions(ray_in, hit_record, scattered_ray) {
+            pdf += diffuse_weight * wi.z / std::f64::consts::PI;
+            pdf += specular_weight
+                * parameters
+                    .specular_distribution()
+                    .visible_normal_pdf(&wo, &m)
+                / (4.0 * wo.dot(&m));
+            pdf += clearcoat_weight * Self::clearcoat_distribution().visible_normal_pdf(&wo, &m)
+                / (4.0 * wo.dot(&m));
+        }
+
+        if transmission_weight > 0.0 {
+            pdf += transmission_weight
+                * self
+                    .transmission(parameters)
+                    .pdf(ray_in, hit_record, scattered_ray);
+        }
+
+        pdf / weights.iter().sum::<f64>()
+    }
+}
+
+impl Material for Principled {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        attenuation: &mut Vector3<f64>,
+        scattered_ray: &mut Ray,
+        rng: &mut dyn RngCore,
+    ) -> bool {
+        let parameters = self.parameters(hit_record);
+        let basis = hit_record.shading_basis();
+        let wo = basis.to_local(&-ray_in.direction().normalize());
+        if wo.z <= 0.0 {
+            return false;
+        }
+
+        // Choose a lobe, with a probability proportional to its weight.
+        let weights = parameters.lobe_weights();
+        let mut choice = rng.gen::<f64>() * weights.iter().sum::<f64>();
+        let lobe = weights
+            .iter()
+            .position(|&weight| {
+                choice -= weight;
+                choice < 0.0
+            })
+            .unwrap_or(weights.len() - 1);
+
+        let direction = match lobe {
+            0 => {
+                let disk = random_in_unit_disk(rng);
+                let wi = Vector3::new(disk.x, disk.y, (1.0 - disk.norm_squared()).max(0.0).sqrt());
+                basis.to_world(&wi)
             }
-            Dielectric(index) => {
-                *attenuation = Vector3::new(1.0, 1.0, 1.0);
-                let refraction_ratio = match hit_record.front_face {
-                    true => 1.0 / index, // ray goes from air to the dielectric
-                    false => index,      // ray goes from the dielectric to the air
+            1 | 2 => {
+                let distribution = match lobe {
+                    1 => parameters.specular_distribution(),
+                    _ => Self::clearcoat_distribution(),
                 };
+                let m = distribution.sample_visible_normal(&wo, rng);
+                let wi = reflect(&-wo, &m);
+                // Reflections going below the surface are lost, otherwise they would be taken for transmissions.
+                if wi.z <= 0.0 {
+                    return false;
+                }
+                basis.to_world(&wi)
+            }
+            _ => {
+                let mut transmitted_ray = Ray::default();
+                if !self.transmission(&parameters).scatter(
+                    ray_in,
+                    hit_record,
+                    &mut Vector3::zeros(),
+                    &mut transmitted_ray,
+                    rng,
+                ) {
+                    return false;
+                }
+                *transmitted_ray.direction()
+            }
+        };
+        *scattered_ray = Ray::new(hit_record.hit_point, direction, ray_in.time());
 
-                let unit_direction = ray_in.direction().normalize();
+        // The attenuation accounts for all the lobes which could have generated the direction.
+        let pdf = self.pdf_lobes(&parameters, ray_in, hit_record, scattered_ray);
+        if pdf <= 0.0 {
+            return false;
+        }
+        *attenuation = self.eval_lobes(&parameters, ray_in, hit_record, scattered_ray) / pdf;
+        true
+    }
 
-                let cos_theta = -unit_direction.dot(&hit_record.normal);
-                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+    fn pdf(&self, ray_in: &Ray, hit_record: &HitRecord, scattered_ray: &Ray) -> f64 {
+        let parameters = self.parameters(hit_record);
+        self.pdf_lobes(&parameters, ray_in, hit_record, scattered_ray)
+    }
 
-                // If sin(theta) is too big, there's total reflexion
-                let direction = match refraction_ratio * sin_theta <= 1.0
-                    || reflectance(cos_theta, refraction_ratio) > rng.gen()
-                {
-                    true => refract(&unit_direction, &hit_record.normal, refraction_ratio),
-                    false => reflect(&unit_direction, &hit_record.normal),
-                };
+    fn eval(&self, ray_in: &Ray, hit_record: &HitRecord, scattered_ray: &Ray) -> Vector3<f64> {
+        let parameters = self.parameters(hit_record);
+        self.eval_lobes(&parameters, ray_in, hit_record, scattered_ray)
+    }
+}
 
-                *scattered_ray = Ray::new(hit_record.hit_point, direction, ray_in.time());
-                true
-            }
+/// An emissive material, turning the surface into an area light.
+pub struct DiffuseLight {
+    emit: Texture,
+    /// If `false`, only the front face of the surface emits light.
+    two_sided: bool,
+}
+
+impl DiffuseLight {
+    /// Creates a light emitting only from the front face of the surface.
+    pub fn new(emit: Texture) -> Self {
+        Self {
+            emit,
+            two_sided: false,
+        }
+    }
+
+    /// Creates a light emitting from both faces of the surface.
+    pub fn two_sided(emit: Texture) -> Self {
+        Self {
+            emit,
+            two_sided: true,
         }
     }
 }
 
-impl Default for Material {
-    fn default() -> Self {
-        Self::Lambertian(Vector3::new(0.5, 0.5, 0.5))
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        _hit_record: &HitRecord,
+        _attenuation: &mut Vector3<f64>,
+        _scattered_ray: &mut Ray,
+        _rng: &mut dyn RngCore,
+    ) -> bool {
+        false
+    }
+
+    fn emitted(&self, _ray_in: &Ray, hit_record: &HitRecord) -> Vector3<f64> {
+        if !hit_record.front_face && !self.two_sided {
+            return Vector3::zeros();
+        }
+
+        self.emit.sample(hit_record)
+    }
+}
+
+/// The phase function of a participating medium scattering light equally in every direction,
+/// used by the volumes of the `volume` module.
+pub struct Isotropic {
+    albedo: Texture,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Texture) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        attenuation: &mut Vector3<f64>,
+        scattered_ray: &mut Ray,
+        rng: &mut dyn RngCore,
+    ) -> bool {
+        *scattered_ray = Ray::new(hit_record.hit_point, random_unit_vector(rng), ray_in.time());
+        *attenuation = self.albedo.sample(hit_record);
+        true
+    }
+
+    fn pdf(&self, _ray_in: &Ray, _hit_record: &HitRecord, _scattered_ray: &Ray) -> f64 {
+        1.0 / (4.0 * std::f64::consts::PI)
+    }
+
+    /// There is no cosine term inside a medium.
+    fn eval(&self, _ray_in: &Ray, hit_record: &HitRecord, _scattered_ray: &Ray) -> Vector3<f64> {
+        self.albedo.sample(hit_record) / (4.0 * std::f64::consts::PI)
+    }
+}
+
+/// Adds detail to the surface of another material, by perturbing its normal with a tangent space normal map.
+/// The red, green and blue channels of the map are the coordinates of the normal along the tangent,
+/// the bitangent and the normal of the shading frame, mapped from `[-1, 1]` to `[0, 1]`.
+pub struct NormalMap {
+    material: Arc<dyn Material>,
+    normal_map: ImageTexture,
+}
+
+impl NormalMap {
+    /// Wraps a material with a normal map, which must have been created with `ImageTexture::linear`.
+    pub fn new(material: Arc<dyn Material>, normal_map: ImageTexture) -> Self {
+        Self {
+            material,
+            normal_map,
+        }
+    }
+
+    /// Wraps a material with a normal map loaded from disk.
+    pub fn load(material: Arc<dyn Material>, path: impl AsRef<Path>) -> ImageResult<Self> {
+        Ok(Self::new(
+            material,
+            ImageTexture::linear(&image::open(path)?),
+        ))
+    }
+
+    fn shading_record(&self, ray_in: &Ray, hit_record: &HitRecord) -> HitRecord {
+        let color = self.normal_map.sample(
+            hit_record.u,
+            hit_record.v,
+            hit_record.duv_dx,
+            hit_record.duv_dy,
+        );
+        let local = 2.0 * color - Vector3::new(1.0, 1.0, 1.0);
+
+        let (tangent, bitangent) = hit_record.shading_frame();
+        let normal = local.x * tangent + local.y * bitangent + local.z * hit_record.normal;
+        with_shading_normal(ray_in, hit_record, normal)
+    }
+}
+
+impl Material for NormalMap {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        attenuation: &mut Vector3<f64>,
+        scattered_ray: &mut Ray,
+        rng: &mut dyn RngCore,
+    ) -> bool {
+        let hit_record = self.shading_record(ray_in, hit_record);
+        self.material
+            .scatter(ray_in, &hit_record, attenuation, scattered_ray, rng)
+    }
+
+    fn emitted(&self, ray_in: &Ray, hit_record: &HitRecord) -> Vector3<f64> {
+        self.material.emitted(ray_in, hit_record)
+    }
+
+    fn pdf(&self, ray_in: &Ray, hit_record: &HitRecord, scattered_ray: &Ray) -> f64 {
+        let hit_record = self.shading_record(ray_in, hit_record);
+        self.material.pdf(ray_in, &hit_record, scattered_ray)
+    }
+
+    fn eval(&self, ray_in: &Ray, hit_record: &HitRecord, scattered_ray: &Ray) -> Vector3<f64> {
+        let hit_record = self.shading_record(ray_in, hit_record);
+        self.material.eval(ray_in, &hit_record, scattered_ray)
+    }
+}
+
+/// Adds detail to the surface of another material, by perturbing its normal as if the surface was displaced
+/// along the normal by a height map. The height is the mean of the color channels of the texture.
+pub struct BumpMap {
+    material: Arc<dyn Material>,
+    height: Texture,
+    /// The displacement corresponding to a height of `1.0`, in world units.
+    scale: f64,
+}
+
+impl BumpMap {
+    pub fn new(material: Arc<dyn Material>, height: Texture, scale: f64) -> Self {
+        Self {
+            material,
+            height,
+            scale,
+        }
+    }
+
+    fn displacement(&self, u: f64, v: f64, p: Point3<f64>) -> f64 {
+        self.scale * self.height.value(u, v, p).mean()
+    }
+
+    fn shading_record(&self, ray_in: &Ray, hit_record: &HitRecord) -> HitRecord {
+        // The displacement is differentiated with finite differences matching the footprint of the pixel.
+        let step = |dx: f64, dy: f64| match 0.5 * (dx.abs() + dy.abs()) {
+            step if step > 0.0 => step,
+            _ => 0.0005,
+        };
+        let du = step(hit_record.duv_dx.x, hit_record.duv_dy.x);
+        let dv = step(hit_record.duv_dx.y, hit_record.duv_dy.y);
+
+        let (u, v, p) = (hit_record.u, hit_record.v, hit_record.hit_point);
+        let displacement = self.displacement(u, v, p);
+        let du_displacement = self.displacement(u + du, v, p + du * hit_record.dpdu);
+        let dv_displacement = self.displacement(u, v + dv, p + dv * hit_record.dpdv);
+
+        // The derivatives of the displaced surface, ignoring the change of the normal itself.
+        let dpdu = hit_record.dpdu + (du_displacement - displacement) / du * hit_record.normal;
+        let dpdv = hit_record.dpdv + (dv_displacement - displacement) / dv * hit_record.normal;
+
+        let mut normal = dpdu.cross(&dpdv);
+        if normal.dot(&hit_record.normal) < 0.0 {
+            normal = -normal;
+        }
+        with_shading_normal(ray_in, hit_record, normal)
+    }
+}
+
+impl Material for BumpMap {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        attenuation: &mut Vector3<f64>,
+        scattered_ray: &mut Ray,
+        rng: &mut dyn RngCore,
+    ) -> bool {
+        let hit_record = self.shading_record(ray_in, hit_record);
+        self.material
+            .scatter(ray_in, &hit_record, attenuation, scattered_ray, rng)
+    }
+
+    fn emitted(&self, ray_in: &Ray, hit_record: &HitRecord) -> Vector3<f64> {
+        self.material.emitted(ray_in, hit_record)
+    }
+
+    fn pdf(&self, ray_in: &Ray, hit_record: &HitRecord, scattered_ray: &Ray) -> f64 {
+        let hit_record = self.shading_record(ray_in, hit_record);
+        self.material.pdf(ray_in, &hit_record, scattered_ray)
+    }
+
+    fn eval(&self, ray_in: &Ray, hit_record: &HitRecord, scattered_ray: &Ray) -> Vector3<f64> {
+        let hit_record = self.shading_record(ray_in, hit_record);
+        self.material.eval(ray_in, &hit_record, scattered_ray)
+    }
+}
+
+/// A copy of the hit record using a perturbed `normal` for shading.
+/// The geometric normal is kept when the perturbed one is degenerate or faces away from the ray,
+/// which would make the surface invisible.
+fn with_shading_normal(ray_in: &Ray, hit_record: &HitRecord, normal: Vector3<f64>) -> HitRecord {
+    let mut shading_record = hit_record.clone();
+    if normal.norm_squared() > 1e-16 && normal.dot(ray_in.direction()) < 0.0 {
+        shading_record.normal = normal.normalize();
+    }
+    shading_record
+}
+
+/// The incident and scattered directions in the shading frame, and the half vector between them,
+/// for a reflection above the surface.
+fn reflection_directions(
+    ray_in: &Ray,
+    hit_record: &HitRecord,
+    scattered_ray: &Ray,
+) -> Option<(Vector3<f64>, Vector3<f64>, Vector3<f64>)> {
+    let basis = hit_record.shading_basis();
+    let wo = basis.to_local(&-ray_in.direction().normalize());
+    let wi = basis.to_local(&scattered_ray.direction().normalize());
+    if wo.z <= 0.0 || wi.z <= 0.0 {
+        return None;
+    }
+
+    let m = (wo + wi).try_normalize(1e-12)?;
+    Some((wo, wi, m))
+}
+
+/// Schlick's weight `(1 - cos)^5` of the Fresnel reflectance at grazing angles.
+fn schlick_weight(cosine: f64) -> f64 {
+    (1.0 - cosine).clamp(0.0, 1.0).powi(5)
+}
+
+/// Generates a scattering direction following a cosine distribution around the normal.
+fn lambertian_direction(hit_record: &HitRecord, rng: &mut dyn RngCore) -> Vector3<f64> {
+    let scatter_direction = hit_record.normal + random_unit_vector(rng);
+
+    // Catch degenerate scatter direction
+    if scatter_direction.norm_squared() < 1e-8 {
+        hit_record.normal
+    } else {
+        scatter_direction
+    }
+}
+
+/// The probability density of `lambertian_direction` generating the direction of `scattered_ray`.
+fn lambertian_pdf(hit_record: &HitRecord, scattered_ray: &Ray) -> f64 {
+    let cosine = hit_record
+        .normal
+        .dot(&scattered_ray.direction().normalize());
+    (cosine / std::f64::consts::PI).max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::f64::consts::PI;
+
+    /// A hit at the origin, on the plane `z = 0` seen from `z > 0`, with the shading frame aligned on the axes.
+    fn hit_record(material: Arc<dyn Material>, front_face: bool) -> HitRecord {
+        let mut hit_record = HitRecord::new(
+            Point3::origin(),
+            Vector3::new(0.0, 0.0, 1.0),
+            material,
+            1.0,
+            0.5,
+            0.5,
+            front_face,
+        );
+        hit_record.dpdu = Vector3::new(1.0, 0.0, 0.0);
+        hit_record.dpdv = Vector3::new(0.0, 1.0, 0.0);
+        hit_record
+    }
+
+    /// The incoming ray, arriving at the origin from the given direction of the upper hemisphere.
+    fn incoming(direction: Vector3<f64>) -> Ray {
+        let direction = direction.normalize();
+        Ray::new(Point3::from(direction), -direction, 0.0)
+    }
+
+    /// The direction of the sphere given by `cos(theta)` and `phi`.
+    fn direction(cos_theta: f64, phi: f64) -> Vector3<f64> {
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+    }
+
+    /// The bin of the sphere containing the direction, out of `COS_BINS * PHI_BINS` bins of equal area.
+    const COS_BINS: usize = 8;
+    const PHI_BINS: usize = 8;
+    fn bin(direction: &Vector3<f64>) -> usize {
+        let cos_bin = ((direction.z + 1.0) / 2.0 * COS_BINS as f64) as usize;
+        let phi = direction.y.atan2(direction.x).rem_euclid(2.0 * PI);
+        let phi_bin = (phi / (2.0 * PI) * PHI_BINS as f64) as usize;
+        cos_bin.min(COS_BINS - 1) * PHI_BINS + phi_bin.min(PHI_BINS - 1)
+    }
+
+    /// Checks that `scatter` samples the directions with the density given by `pdf`,
+    /// and that its attenuation is `eval / pdf`. The material must not have specular lobes.
+    fn assert_consistent_sampling(material: Arc<dyn Material>, incoming: &Ray, front_face: bool) {
+        let hit_record = hit_record(material.clone(), front_face);
+        let mut rng = StdRng::seed_from_u64(7);
+
+        const SAMPLES: usize = 20_000;
+        let mut histogram = [0.0; COS_BINS * PHI_BINS];
+        for sample in 0..SAMPLES {
+            let mut attenuation = Vector3::zeros();
+            let mut scattered = Ray::default();
+            if !material.scatter(
+                incoming,
+                &hit_record,
+                &mut attenuation,
+                &mut scattered,
+                &mut rng,
+            ) {
+                continue;
+            }
+
+            histogram[bin(&scattered.direction().normalize())] += 1.0 / SAMPLES as f64;
+            if sample % 8 != 0 {
+                continue;
+            }
+
+            let pdf = material.pdf(incoming, &hit_record, &scattered);
+            assert!(pdf > 0.0, "a sampled direction has a null density");
+            let expected = material.eval(incoming, &hit_record, &scattered) / pdf;
+            assert!(
+                (expected - attenuation).norm() <= 1e-6 * expected.norm().max(1.0),
+                "eval / pdf = {expected:?}, but scatter attenuates by {attenuation:?}"
+            );
+        }
+
+        // The integral of the density over each bin, by the midpoint rule over `theta` and `phi`,
+        // which resolves the lobes around the poles.
+        const THETA_STEPS: usize = 512;
+        const PHI_STEPS: usize = 128;
+        let mut integrals = [0.0; COS_BINS * PHI_BINS];
+        let (theta_step, phi_step) = (PI / THETA_STEPS as f64, 2.0 * PI / PHI_STEPS as f64);
+        for i in 0..THETA_STEPS {
+            let theta = (i as f64 + 0.5) * theta_step;
+            for j in 0..PHI_STEPS {
+                let direction = direction(theta.cos(), (j as f64 + 0.5) * phi_step);
+                let scattered = Ray::new(Point3::origin(), direction, 0.0);
+                integrals[bin(&direction)] += material.pdf(incoming, &hit_record, &scattered)
+                    * theta.sin()
+                    * theta_step
+                    * phi_step;
+            }
+        }
+
+        for (frequency, integral) in histogram.iter().zip(&integrals) {
+            assert!(
+                (frequency - integral).abs() < 0.01,
+                "sampled {frequency}, but the pdf integrates to {integral}\n{histogram:?}\n{integrals:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn rough_dielectric_sampling() {
+        for (index, roughness) in [(1.5, 0.5), (1.33, 0.7)] {
+            let material = Arc::new(RoughDielectric::new(
+                index,
+                Ggx::from_roughness(roughness, 0.0),
+            ));
+            for direction in [Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.6, 0.2, 0.5)] {
+                for front_face in [true, false] {
+                    assert_consistent_sampling(material.clone(), &incoming(direction), front_face);
+                }
+            }
+        }
+    }
+    fn constant(value: f64) -> Texture {
+        Texture::SolidColor(Vector3::repeat(value))
+    }
+
+    #[test]
+    fn principled_sampling() {
+        // (metallic, roughness, sheen, clearcoat, transmission)
+        for (metallic, roughness, sheen, clearcoat, transmission) in [
+            (0.0, 0.5, 0.0, 0.0, 0.0),
+            (0.3, 0.6, 0.5, 0.5, 0.4),
+            // Without diffuse nor transmission lobes.
+            (1.0, 0.5, 0.0, 0.0, 0.0),
+            // Without diffuse lobe.
+            (0.0, 0.6, 0.0, 0.0, 1.0),
+        ] {
+            let mut material = Principled::new(Texture::SolidColor(Vector3::new(0.8, 0.5, 0.2)));
+            material.set_metallic(constant(metallic));
+            material.set_roughness(constant(roughness));
+            material.set_sheen(constant(sheen));
+            material.set_clearcoat(constant(clearcoat));
+            material.set_transmission(constant(transmission));
+            let material = Arc::new(material);
+
+            // The back face only differs from the front face for the transmission.
+            let faces: &[bool] = match transmission > 0.0 {
+                true => &[true, false],
+                false => &[true],
+            };
+            for direction in [Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.6, 0.2, 0.5)] {
+                for &front_face in faces {
+                    assert_consistent_sampling(material.clone(), &incoming(direction), front_face);
+                }
+            }
+        }
+    }
+    /// The fraction of `Dielectric` scatterings going back to the side of the incoming ray.
+    fn dielectric_reflected_fraction(
+        material: &Dielectric,
+        incoming: &Ray,
+        front_face: bool,
+    ) -> f64 {
+        let hit_record = hit_record(Arc::new(Dielectric::new(1.0)), front_face);
+        let mut rng = StdRng::seed_from_u64(5);
+
+        let mut reflected = 0;
+        for _ in 0..10_000 {
+            let mut scattered = Ray::default();
+            assert!(material.scatter(
+                incoming,
+                &hit_record,
+                &mut Vector3::zeros(),
+                &mut scattered,
+                &mut rng
+            ));
+            if scattered.direction().z > 0.0 {
+                reflected += 1;
+            }
+        }
+        reflected as f64 / 10_000.0
+    }
+
+    #[test]
+    fn dielectric_reflection() {
+        let glass = Dielectric::new(1.5);
+
+        // About 4% of the light is reflected at normal incidence, on both sides.
+        for front_face in [true, false] {
+            let fraction = dielectric_reflected_fraction(
+                &glass,
+                &incoming(Vector3::new(0.0, 0.0, 1.0)),
+                front_face,
+            );
+            assert!((fraction - 0.04).abs() < 0.01, "{fraction}");
+        }
+
+        // Total internal reflection beyond the critical angle, and strong reflection at grazing angles.
+        let grazing = incoming(Vector3::new(0.9, 0.0, 0.2));
+        assert_eq!(dielectric_reflected_fraction(&glass, &grazing, false), 1.0);
+        let fraction = dielectric_reflected_fraction(&glass, &grazing, true);
+        assert!(fraction > 0.2 && fraction < 1.0, "{fraction}");
+    }
+
+    #[test]
+    fn dielectric_absorption() {
+        let (a, d) = (0.7, 2.5);
+        let glass = Dielectric::with_absorption(1.5, Vector3::new(a, 0.0, 0.0));
+        let mut rng = StdRng::seed_from_u64(0);
+        let ray = Ray::new(Point3::new(0.0, 0.0, d), Vector3::new(0.0, 0.0, -1.0), 0.0);
+
+        let mut hit_record = hit_record(Arc::new(Dielectric::new(1.0)), false);
+        hit_record.t = d;
+        let mut attenuation = Vector3::zeros();
+        glass.scatter(
+            &ray,
+            &hit_record,
+            &mut attenuation,
+            &mut Ray::default(),
+            &mut rng,
+        );
+        assert!((attenuation - Vector3::new((-a * d).exp(), 1.0, 1.0)).norm() < 1e-12);
+
+        // Entering the material does not attenuate the light.
+        hit_record.front_face = true;
+        glass.scatter(
+            &ray,
+            &hit_record,
+            &mut attenuation,
+            &mut Ray::default(),
+            &mut rng,
+        );
+        assert_eq!(attenuation, Vector3::new(1.0, 1.0, 1.0));
+    }
+
+    /// A normal map whose every pixel has the given color.
+    fn normal_map(color: [f32; 3]) -> NormalMap {
+        let image = image::Rgb32FImage::from_pixel(1, 1, image::Rgb(color));
+        let texture = ImageTexture::linear(&image::DynamicImage::ImageRgb32F(image));
+        NormalMap::new(Arc::new(Lambertian::default()), texture)
+    }
+
+    #[test]
+    fn flat_normal_map() {
+        let hit_record = hit_record(Arc::new(Lambertian::default()), true);
+        let ray = incoming(Vector3::new(0.2, 0.3, 1.0));
+
+        let shading_record = normal_map([0.5, 0.5, 1.0]).shading_record(&ray, &hit_record);
+        assert!((shading_record.normal - hit_record.normal).norm() < 1e-6);
+
+        let flat = BumpMap::new(Arc::new(Lambertian::default()), constant(0.7), 0.1);
+        let shading_record = flat.shading_record(&ray, &hit_record);
+        assert!((shading_record.normal - hit_record.normal).norm() < 1e-12);
+    }
+
+    #[test]
+    fn tilted_normal_map() {
+        let hit_record = hit_record(Arc::new(Lambertian::default()), true);
+        let ray = incoming(Vector3::new(0.2, 0.3, 1.0));
+
+        // A normal tilted by 45 degrees towards the tangent, then towards the bitangent.
+        let tilt = 0.5 + 0.5 * std::f32::consts::FRAC_1_SQRT_2;
+        let tilted = Vector3::new(1.0, 0.0, 1.0).normalize();
+        let shading_record = normal_map([tilt, 0.5, tilt]).shading_record(&ray, &hit_record);
+        assert!((shading_record.normal - tilted).norm() < 1e-6);
+
+        let tilted = Vector3::new(0.0, 1.0, 1.0).normalize();
+        let shading_record = normal_map([0.5, tilt, tilt]).shading_record(&ray, &hit_record);
+        assert!((shading_record.normal - tilted).norm() < 1e-6);
     }
 }
@@ -0,0 +1,302 @@
+use nalgebra::{Point3, Vector3};
+use rand::RngCore;
+use real_interval::RealInterval;
+use std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::bvh::BVHNode;
+use crate::geometry::{intersect_triangle, triangle_bounding_box, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::world::HitRecord;
+
+/// An indexed triangle mesh: the vertices, and their optional normals and texture coordinates,
+/// are stored once and shared between the faces.
+pub struct TriangleMesh {
+    positions: Vec<Point3<f64>>,
+    /// The per-vertex normals, used for smooth shading.
+    normals: Option<Vec<Vector3<f64>>>,
+    /// The per-vertex texture coordinates.
+    uvs: Option<Vec<(f64, f64)>>,
+    /// The per-vertex colors, used by the `VertexColor` material.
+    colors: Option<Vec<Vector3<f64>>>,
+    /// The indices of the three vertices of each face.
+    indices: Vec<[usize; 3]>,
+    material: Arc<dyn Material>,
+}
+
+impl TriangleMesh {
+    /// Creates a flat-shaded mesh from the vertices positions and the indices of each face.
+    pub fn new(
+        positions: Vec<Point3<f64>>,
+        indices: Vec<[usize; 3]>,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        assert!(
+            indices.iter().flatten().all(|&i| i < positions.len()),
+            "a face references a vertex that does not exist"
+        );
+
+        Self {
+            positions,
+            normals: None,
+            uvs: None,
+            colors: None,
+            indices,
+            material,
+        }
+    }
+
+    /// Sets the per-vertex normals, interpolated across each face for smooth shading.
+    pub fn set_normals(&mut self, normals: Vec<Vector3<f64>>) {
+        assert_eq!(normals.len(), self.positions.len());
+        self.normals = Some(normals);
+    }
+
+    /// Sets the per-vertex texture coordinates, interpolated across each face.
+    pub fn set_uvs(&mut self, uvs: Vec<(f64, f64)>) {
+        assert_eq!(uvs.len(), self.positions.len());
+        self.uvs = Some(uvs);
+    }
+
+    /// Sets the per-vertex colors, interpolated across each face.
+    pub fn set_colors(&mut self, colors: Vec<Vector3<f64>>) {
+        assert_eq!(colors.len(), self.positions.len());
+        self.colors = Some(colors);
+    }
+
+    /// The number of faces of the mesh.
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Splits the mesh into its faces, each one being a hittable sharing the data of the mesh.
+    pub fn into_triangles(self) -> Vec<MeshTriangle> {
+        let mesh = Arc::new(self);
+
+        (0..mesh.indices.len())
+            .map(|face| MeshTriangle::new(mesh.clone(), face))
+            .collect()
+    }
+
+    /// Builds a Bounding Volume Hierarchy over the faces of the mesh.
+    pub fn into_bvh(self) -> BVHNode {
+        assert!(!self.is_empty(), "cannot build a BVH over an empty mesh");
+
+        BVHNode::from_objects(
+            self.into_triangles()
+                .into_iter()
+                .map(|triangle| Box::new(triangle) as Box<dyn Hittable + Sync>)
+                .collect(),
+        )
+    }
+}
+
+/// A face of a `TriangleMesh`.
+pub struct MeshTriangle {
+    mesh: Arc<TriangleMesh>,
+    face: usize,
+    bbox: AABB,
+}
+
+impl MeshTriangle {
+    fn new(mesh: Arc<TriangleMesh>, face: usize) -> Self {
+        let [i0, i1, i2] = mesh.indices[face];
+        let bbox = triangle_bounding_box(
+            &mesh.positions[i0],
+            &mesh.positions[i1],
+            &mesh.positions[i2],
+        );
+
+        Self { mesh, face, bbox }
+    }
+
+    /// The derivatives of the position with respect to the texture coordinates, or to the barycentric
+    /// coordinates when the mesh has no texture coordinates, or when they are degenerate.
+    fn derivatives(
+        &self,
+        p0: &Point3<f64>,
+        p1: &Point3<f64>,
+        p2: &Point3<f64>,
+    ) -> (Vector3<f64>, Vector3<f64>) {
+        let edges = (p1 - p0, p2 - p0);
+        let Some(uvs) = &self.mesh.uvs else {
+            return edges;
+        };
+
+        let [i0, i1, i2] = self.mesh.indices[self.face];
+        let duv02 = (uvs[i0].0 - uvs[i2].0, uvs[i0].1 - uvs[i2].1);
+        let duv12 = (uvs[i1].0 - uvs[i2].0, uvs[i1].1 - uvs[i2].1);
+        let (dp02, dp12) = (p0 - p2, p1 - p2);
+
+        let determinant = duv02.0 * duv12.1 - duv02.1 * duv12.0;
+        if determinant.abs() < 1e-12 {
+            return edges;
+        }
+
+        (
+            (duv12.1 * dp02 - duv02.1 * dp12) / determinant,
+            (duv02.0 * dp12 - duv12.0 * dp02) / determinant,
+        )
+    }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_interval: RealInterval,
+        hit_record: &mut HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> bool {
+        let [i0, i1, i2] = self.mesh.indices[self.face];
+        let (p0, p1, p2) = (
+            &self.mesh.positions[i0],
+            &self.mesh.positions[i1],
+            &self.mesh.positions[i2],
+        );
+
+        let Some((t, b1, b2)) = intersect_triangle(ray, t_interval, p0, p1, p2) else {
+            return false;
+        };
+        let b0 = 1.0 - b1 - b2;
+
+        hit_record.t = t;
+        hit_record.hit_point = ray.at(t);
+        hit_record.material = self.mesh.material.clone();
+
+        (hit_record.u, hit_record.v) = match &self.mesh.uvs {
+            Some(uvs) => (
+                b0 * uvs[i0].0 + b1 * uvs[i1].0 + b2 * uvs[i2].0,
+                b0 * uvs[i0].1 + b1 * uvs[i1].1 + b2 * uvs[i2].1,
+            ),
+            None => (b1, b2),
+        };
+        (hit_record.dpdu, hit_record.dpdv) = self.derivatives(p0, p1, p2);
+        hit_record.vertex_color = self
+            .mesh
+            .colors
+            .as_ref()
+            .map(|colors| b0 * colors[i0] + b1 * colors[i1] + b2 * colors[i2]);
+
+        // The geometric normal decides which face is hit, the interpolated normal is used for shading.
+        let geometric_normal = (p1 - p0).cross(&(p2 - p0)).normalize();
+        hit_record.set_face_normal(ray, &geometric_normal);
+
+        if let Some(normals) = &self.mesh.normals {
+            let mut shading_normal = b0 * normals[i0] + b1 * normals[i1] + b2 * normals[i2];
+            if shading_normal.norm_squared() > 0.0 {
+                shading_normal.normalize_mut();
+
+                // Keep the shading normal on the side of the face given by the winding order.
+                if shading_normal.dot(&geometric_normal) < 0.0 {
+                    shading_normal = -shading_normal;
+                }
+                hit_record.normal = match hit_record.front_face {
+                    true => shading_normal,
+                    false => -shading_normal,
+                };
+            }
+        }
+
+        true
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        &self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    /// The normals of the vertices, tilted outwards.
+    fn normals() -> Vec<Vector3<f64>> {
+        vec![
+            Vector3::new(0.0, 0.0, 1.0),
+            Vector3::new(1.0, 0.0, 1.0).normalize(),
+            Vector3::new(0.0, 1.0, 1.0).normalize(),
+        ]
+    }
+
+    /// A single triangle in the plane `z = 0`, with texture coordinates and smooth normals.
+    fn triangle() -> MeshTriangle {
+        let mut mesh = TriangleMesh::new(
+            vec![
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(1.0, 0.0, 0.0),
+                Point3::new(0.0, 1.0, 0.0),
+            ],
+            vec![[0, 1, 2]],
+            Arc::new(Lambertian::default()),
+        );
+        mesh.set_uvs(vec![(0.5, 0.0), (1.0, 0.5), (0.0, 1.0)]);
+        mesh.set_normals(normals());
+
+        mesh.into_triangles().pop().unwrap()
+    }
+
+    fn hit(triangle: &MeshTriangle, origin: Point3<f64>, direction: Vector3<f64>) -> HitRecord {
+        let ray = Ray::new(origin, direction, 0.0);
+        let mut hit_record = HitRecord::default();
+        assert!(triangle.hit(
+            &ray,
+            RealInterval::min_max(0.001, f32::INFINITY),
+            &mut hit_record,
+            &mut rand::thread_rng(),
+        ));
+        hit_record
+    }
+
+    #[test]
+    fn interpolated_attributes() {
+        let triangle = triangle();
+
+        // At the barycentric coordinates `(0.5, 0.2, 0.3)`.
+        let hit_record = hit(
+            &triangle,
+            Point3::new(0.2, 0.3, 1.0),
+            Vector3::new(0.0, 0.0, -1.0),
+        );
+        assert!((hit_record.t - 1.0).abs() < 1e-12);
+        assert!((hit_record.u - 0.45).abs() < 1e-12 && (hit_record.v - 0.4).abs() < 1e-12);
+
+        let normals = normals();
+        let normal = (0.5 * normals[0] + 0.2 * normals[1] + 0.3 * normals[2]).normalize();
+        assert!((hit_record.normal - normal).norm() < 1e-12);
+        assert!(hit_record.front_face);
+
+        // From below, the interpolated normal is flipped towards the ray.
+        let hit_record = hit(
+            &triangle,
+            Point3::new(0.2, 0.3, -1.0),
+            Vector3::new(0.0, 0.0, 1.0),
+        );
+        assert!((hit_record.normal + normal).norm() < 1e-12);
+        assert!(!hit_record.front_face);
+    }
+
+    #[test]
+    fn derivatives_follow_texture_coordinates() {
+        let hit_record = hit(
+            &triangle(),
+            Point3::new(0.2, 0.3, 1.0),
+            Vector3::new(0.0, 0.0, -1.0),
+        );
+
+        // The texture coordinates change by `(0.5 x - 0.5 y, 0.5 x + y)` when moving by `(x, y)`,
+        // so that moving by `dpdu` or `dpdv` changes them by one along `u` or `v` only.
+        let duv = |dp: Vector3<f64>| (0.5 * dp.x - 0.5 * dp.y, 0.5 * dp.x + dp.y);
+        let (du, dv) = (duv(hit_record.dpdu), duv(hit_record.dpdv));
+        assert!((du.0 - 1.0).abs() < 1e-12 && du.1.abs() < 1e-12);
+        assert!(dv.0.abs() < 1e-12 && (dv.1 - 1.0).abs() < 1e-12);
+        assert_eq!(hit_record.dpdu.z, 0.0);
+        assert_eq!(hit_record.dpdv.z, 0.0);
+    }
+}
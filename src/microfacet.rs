@@ -0,0 +1,241 @@
+//! Microfacet models of rough surfaces, seen as a distribution of tiny perfect mirrors.
+//! The functions work in the local shading frame, where the normal of the surface is the `z` axis,
+//! and the tangent is the `x` axis.
+
+use nalgebra::Vector3;
+use rand::{Rng, RngCore};
+
+/// The roughness below which a surface is considered perfectly smooth, and sampled as a mirror.
+const SMOOTH_ALPHA: f64 = 1e-3;
+
+/// The GGX, or Trowbridge-Reitz, distribution of the normals of the microfacets.
+/// The roughness along the tangent and the bitangent may differ, to model brushed surfaces.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ggx {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl Ggx {
+    /// Creates a distribution from its width along the tangent and the bitangent.
+    pub fn new(alpha_x: f64, alpha_y: f64) -> Self {
+        assert!(alpha_x >= 0.0 && alpha_y >= 0.0);
+
+        Self {
+            alpha_x: alpha_x.max(1e-4),
+            alpha_y: alpha_y.max(1e-4),
+        }
+    }
+
+    /// Creates a distribution from a perceptual `roughness` in `[0, 1]`, squared to get the width,
+    /// and an `anisotropy` in `[0, 1]` stretching the highlights along the tangent.
+    pub fn from_roughness(roughness: f64, anisotropy: f64) -> Self {
+        let alpha = roughness.clamp(0.0, 1.0).powi(2);
+        let aspect = (1.0 - 0.9 * anisotropy.clamp(0.0, 1.0)).sqrt();
+        Self::new(alpha / aspect, alpha * aspect)
+    }
+
+    /// If the surface is smooth enough to be treated as a perfect mirror.
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < SMOOTH_ALPHA
+    }
+
+    /// The density of microfacets with the normal `m`, with respect to the projected area.
+    pub fn d(&self, m: &Vector3<f64>) -> f64 {
+        if m.z <= 0.0 {
+            return 0.0;
+        }
+
+        let x = m.x / self.alpha_x;
+        let y = m.y / self.alpha_y;
+        let denominator = x * x + y * y + m.z * m.z;
+        1.0 / (std::f64::consts::PI * self.alpha_x * self.alpha_y * denominator * denominator)
+    }
+
+    /// Smith's auxiliary function, measuring the microfacets hidden when seen from `w`.
+    pub fn lambda(&self, w: &Vector3<f64>) -> f64 {
+        if w.z == 0.0 {
+            return f64::INFINITY;
+        }
+
+        let x = self.alpha_x * w.x;
+        let y = self.alpha_y * w.y;
+        let tan2 = (x * x + y * y) / (w.z * w.z);
+        0.5 * ((1.0 + tan2).sqrt() - 1.0)
+    }
+
+    /// The fraction of microfacets visible from `w`.
+    pub fn g1(&self, w: &Vector3<f64>) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// The fraction of microfacets visible from both `wo` and `wi`, using the height-correlated masking-shadowing.
+    pub fn g(&self, wo: &Vector3<f64>, wi: &Vector3<f64>) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Samples the normal of a microfacet visible from `wo`, following Heitz,
+    /// "Sampling the GGX Distribution of Visible Normals" (2018).
+    pub fn sample_visible_normal(&self, wo: &Vector3<f64>, rng: &mut dyn RngCore) -> Vector3<f64> {
+        // Stretch the view direction, to sample the normals of a hemisphere.
+        let wh = Vector3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).normalize();
+
+        let length2 = wh.x * wh.x + wh.y * wh.y;
+        let t1 = match length2 > 0.0 {
+            true => Vector3::new(-wh.y, wh.x, 0.0) / length2.sqrt(),
+            false => Vector3::new(1.0, 0.0, 0.0),
+        };
+        let t2 = wh.cross(&t1);
+
+        // A point of the disk, warped towards the part of the hemisphere visible from `wh`.
+        let r = rng.gen::<f64>().sqrt();
+        let phi = 2.0 * std::f64::consts::PI * rng.gen::<f64>();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + wh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * wh;
+
+        // Unstretch the normal.
+        Vector3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(1e-6)).normalize()
+    }
+
+    /// The probability density of `sample_visible_normal` generating the normal `m`, seen from `wo`.
+    pub fn visible_normal_pdf(&self, wo: &Vector3<f64>, m: &Vector3<f64>) -> f64 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+
+        self.g1(wo) * wo.dot(m).max(0.0) * self.d(m) / wo.z
+    }
+}
+
+/// The reflectance of a conductor, with the complex index of refraction `eta + i k`,
+/// for light arriving with the cosine `cos_theta` to the normal. Computed for each color channel.
+pub fn fresnel_conductor(cos_theta: f64, eta: &Vector3<f64>, k: &Vector3<f64>) -> Vector3<f64> {
+    let cos2 = cos_theta.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+
+    Vector3::from_fn(|channel, _| {
+        let eta2 = eta[channel] * eta[channel];
+        let k2 = k[channel] * k[channel];
+
+        let t0 = eta2 - k2 - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+
+        let t1 = a2_plus_b2 + cos2;
+        let t2 = 2.0 * cos_theta.clamp(0.0, 1.0) * a;
+        let rs = (t1 - t2) / (t1 + t2);
+
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+
+        0.5 * (rs + rp)
+    })
+}
+
+/// The reflectance of the interface between two dielectrics, for light arriving with the cosine `cos_theta`
+/// to the normal, where `eta` is the ratio of the index of the other side to the index of the side of the light.
+/// Returns `1.0` when the light is totally reflected.
+pub fn fresnel_dielectric(cos_theta: f64, eta: f64) -> f64 {
+    let cos_i = cos_theta.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::f64::consts::PI;
+
+    fn distributions() -> [Ggx; 3] {
+        [
+            Ggx::new(0.5, 0.5),
+            Ggx::new(0.2, 0.6),
+            Ggx::from_roughness(0.3, 0.0),
+        ]
+    }
+
+    #[test]
+    fn normalized_distribution() {
+        // The projected area of the microfacets is the area of the surface: the integral of `D cos` is 1.
+        const THETA_STEPS: usize = 4096;
+        const PHI_STEPS: usize = 128;
+        let (theta_step, phi_step) = (0.5 * PI / THETA_STEPS as f64, 2.0 * PI / PHI_STEPS as f64);
+
+        for distribution in distributions() {
+            let mut integral = 0.0;
+            for i in 0..THETA_STEPS {
+                let theta = (i as f64 + 0.5) * theta_step;
+                for j in 0..PHI_STEPS {
+                    let phi = (j as f64 + 0.5) * phi_step;
+                    let m = Vector3::new(
+                        theta.sin() * phi.cos(),
+                        theta.sin() * phi.sin(),
+                        theta.cos(),
+                    );
+                    integral += distribution.d(&m) * m.z * theta.sin() * theta_step * phi_step;
+                }
+            }
+            assert!(
+                (integral - 1.0).abs() < 1e-3,
+                "{distribution:?}: {integral}"
+            );
+        }
+    }
+
+    #[test]
+    fn visible_normals_are_in_upper_hemisphere() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for distribution in distributions() {
+            for wo in [
+                Vector3::new(0.0, 0.0, 1.0),
+                Vector3::new(0.6, -0.3, 0.4).normalize(),
+                Vector3::new(0.99, 0.0, 0.01).normalize(),
+            ] {
+                for _ in 0..1000 {
+                    let m = distribution.sample_visible_normal(&wo, &mut rng);
+                    assert!(m.z > 0.0, "{m:?}");
+                    assert!((m.norm() - 1.0).abs() < 1e-9);
+                    assert!(distribution.visible_normal_pdf(&wo, &m) >= 0.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn conductor_normal_incidence() {
+        let eta = Vector3::new(0.2, 0.9, 1.5);
+        let k = Vector3::new(3.6, 2.6, 0.0);
+        let reflectance = fresnel_conductor(1.0, &eta, &k);
+
+        for channel in 0..3 {
+            let (n, k) = (eta[channel], k[channel]);
+            let expected = ((n - 1.0).powi(2) + k * k) / ((n + 1.0).powi(2) + k * k);
+            assert!((reflectance[channel] - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn dielectric_reflectance() {
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-9);
+        assert!((fresnel_dielectric(1.0, 1.0 / 1.5) - 0.04).abs() < 1e-9);
+
+        // Leaving glass, the critical angle is at `sin = 1 / 1.5`.
+        let critical_cos = f64::sqrt(1.0 - 1.0 / (1.5 * 1.5));
+        assert!(fresnel_dielectric(critical_cos + 0.01, 1.0 / 1.5) < 1.0);
+        assert_eq!(fresnel_dielectric(critical_cos - 0.01, 1.0 / 1.5), 1.0);
+        assert_eq!(fresnel_dielectric(0.0, 1.0 / 1.5), 1.0);
+    }
+}
@@ -0,0 +1,123 @@
+use nalgebra::{Point3, Vector3};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+use crate::utility::random_unit_vector;
+
+/// The number of gradients of the noise, after which the noise repeats itself.
+const POINT_COUNT: usize = 256;
+
+/// A Perlin gradient noise generator. The same seed always generates the same noise.
+pub struct Perlin {
+    /// The random unit gradients at the lattice points.
+    gradients: Vec<Vector3<f64>>,
+    /// The permutations hashing the coordinates of a lattice point along each axis.
+    permutations: [Vec<usize>; 3],
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let gradients = (0..POINT_COUNT)
+            .map(|_| random_unit_vector(&mut rng))
+            .collect();
+        let permutations = std::array::from_fn(|_| {
+            let mut permutation: Vec<usize> = (0..POINT_COUNT).collect();
+            permutation.shuffle(&mut rng);
+            permutation
+        });
+
+        Self {
+            gradients,
+            permutations,
+        }
+    }
+
+    /// The noise at the given point, in `[-1, 1]`. The features of the noise have a size of about 1.
+    pub fn noise(&self, p: &Point3<f64>) -> f64 {
+        let floor = p.map(f64::floor);
+        let fraction = p - floor;
+        // Hermite smoothing, to hide the lattice.
+        let smooth = fraction.map(|f| f * f * (3.0 - 2.0 * f));
+
+        let mut sum = 0.0;
+        for corner in 0..8 {
+            let offset = Vector3::new(
+                (corner & 1) as f64,
+                ((corner >> 1) & 1) as f64,
+                ((corner >> 2) & 1) as f64,
+            );
+
+            let lattice = floor + offset;
+            let hash = self.permutations[0][lattice.x as i64 as usize & (POINT_COUNT - 1)]
+                ^ self.permutations[1][lattice.y as i64 as usize & (POINT_COUNT - 1)]
+                ^ self.permutations[2][lattice.z as i64 as usize & (POINT_COUNT - 1)];
+
+            let weight = Vector3::from_fn(|axis, _| match offset[axis] == 1.0 {
+                true => smooth[axis],
+                false => 1.0 - smooth[axis],
+            });
+            sum += weight.product() * self.gradients[hash].dot(&(fraction - offset));
+        }
+
+        sum
+    }
+
+    /// The sum of `depth` octaves of noise, each one of double frequency and half amplitude,
+    /// in absolute value. Gives a turbulent pattern in `[0, 2]`.
+    pub fn turbulence(&self, p: &Point3<f64>, depth: usize) -> f64 {
+        let mut sum = 0.0;
+        let mut point = *p;
+        let mut weight = 1.0;
+
+        for _ in 0..depth {
+            sum += weight * self.noise(&point);
+            weight *= 0.5;
+            point *= 2.0;
+        }
+
+        sum.abs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    fn points() -> Vec<Point3<f64>> {
+        let mut rng = StdRng::seed_from_u64(11);
+        (0..10_000)
+            .map(|_| Point3::from(Vector3::from_fn(|_, _| rng.gen_range(-50.0..50.0))))
+            .collect()
+    }
+
+    #[test]
+    fn deterministic() {
+        let (a, b, other) = (Perlin::new(42), Perlin::new(42), Perlin::new(43));
+
+        let points = points();
+        assert!(points.iter().all(|p| a.noise(p) == b.noise(p)));
+        assert!(points
+            .iter()
+            .all(|p| a.turbulence(p, 7) == b.turbulence(p, 7)));
+        assert!(points.iter().any(|p| a.noise(p) != other.noise(p)));
+    }
+
+    #[test]
+    fn bounded() {
+        let perlin = Perlin::new(0);
+
+        for p in points() {
+            let noise = perlin.noise(&p);
+            assert!((-1.0..=1.0).contains(&noise), "{noise}");
+            let turbulence = perlin.turbulence(&p, 7);
+            assert!((0.0..=2.0).contains(&turbulence), "{turbulence}");
+        }
+        // The noise is null on the lattice points, and varies between them.
+        assert_eq!(perlin.noise(&Point3::new(3.0, -2.0, 7.0)), 0.0);
+        assert_ne!(perlin.noise(&Point3::new(3.3, -2.7, 7.1)), 0.0);
+    }
+}
@@ -2,6 +2,7 @@ use nalgebra::{Point3, Vector3};
 use rand::RngCore;
 use real_interval::RealInterval;
 
+use crate::utility::power_heuristic;
 use crate::world::{HitRecord, World};
 
 #[derive(Default)]
@@ -11,6 +12,19 @@ pub struct Ray {
     /// The instant when the ray is emitted.
     /// Used to determine the position of moving objects when the ray collides them.
     time: f64,
+    /// The rays offset by one pixel, used to estimate the area of a surface seen by the ray.
+    differential: Option<RayDifferential>,
+}
+
+/// Two auxiliary rays, offset from a ray by one pixel horizontally (`x`) and vertically (`y`) on the image.
+/// They follow the ray through specular bounces, and give the footprint of a pixel on the surfaces,
+/// used to filter the textures.
+#[derive(Debug, Clone, Copy)]
+pub struct RayDifferential {
+    pub x_origin: Point3<f64>,
+    pub x_direction: Vector3<f64>,
+    pub y_origin: Point3<f64>,
+    pub y_direction: Vector3<f64>,
 }
 
 impl Ray {
@@ -19,9 +33,20 @@ impl Ray {
             origin,
             direction,
             time,
+            differential: None,
         }
     }
 
+    /// Returns the ray with the given differential.
+    pub fn with_differential(mut self, differential: RayDifferential) -> Self {
+        self.differential = Some(differential);
+        self
+    }
+
+    pub fn differential(&self) -> Option<&RayDifferential> {
+        self.differential.as_ref()
+    }
+
     pub fn origin(&self) -> &Point3<f64> {
         &self.origin
     }
@@ -42,6 +67,19 @@ impl Ray {
 
     /// Computes the color of the surface hit by the ray.
     pub fn color(&self, depth: usize, world: &World, rng: &mut dyn RngCore) -> Vector3<f64> {
+        self.trace(depth, world, rng, None)
+    }
+
+    /// Computes the color of the surface hit by the ray.
+    /// `scatter_pdf` is the probability density with which the material hit by the previous ray
+    /// generated this ray, or `None` for camera rays and specular bounces.
+    fn trace(
+        &self,
+        depth: usize,
+        world: &World,
+        rng: &mut dyn RngCore,
+        scatter_pdf: Option<f64>,
+    ) -> Vector3<f64> {
         let mut hit_record = HitRecord::default();
 
         // Max depth is exceeded, the ray will stop bouncing.
@@ -54,23 +92,121 @@ impl Ray {
             self,
             RealInterval::min_max(0.001, f32::INFINITY), // 0.001 to limit "shadown acne"
             &mut hit_record,
+            rng,
         ) {
+            if let Some(differential) = &self.differential {
+                hit_record.set_uv_derivatives(differential);
+            }
+
             let mut bouncing_ray = Ray::default();
             let mut attenuation = Vector3::default();
-            let material = hit_record.material;
+            let material = &hit_record.material;
+            let emitted = material.emitted(self, &hit_record);
 
-            if material.scatter(self, &hit_record, &mut attenuation, &mut bouncing_ray, rng) {
-                attenuation.component_mul(&bouncing_ray.color(depth - 1, world, rng))
-            } else {
-                Vector3::zeros()
+            if !material.scatter(self, &hit_record, &mut attenuation, &mut bouncing_ray, rng) {
+                return emitted;
             }
+
+            let pdf = material.pdf(self, &hit_record, &bouncing_ray);
+            if pdf == 0.0 {
+                // Specular bounce: the background cannot be sampled directly.
+                // The footprint of the pixel follows the ray, to keep filtering the textures seen in mirrors.
+                bouncing_ray.differential = self.specular_differential(&hit_record, &bouncing_ray);
+                return emitted
+                    + attenuation.component_mul(&bouncing_ray.trace(depth - 1, world, rng, None));
+            }
+
+            emitted
+                + self.sample_background(&hit_record, world, rng)
+                + attenuation.component_mul(&bouncing_ray.trace(depth - 1, world, rng, Some(pdf)))
         } else {
-            // Display a blue gradient for background.
-            let unit_direction = self.direction.normalize();
-            let a = 0.5 * (unit_direction.y + 1.0);
+            let background = world.background();
+            let color = background.color(self);
+
+            match scatter_pdf {
+                // The background may also have been sampled directly from the previous hit.
+                Some(pdf) => power_heuristic(pdf, background.pdf(self.direction())) * color,
+                None => color,
+            }
+        }
+    }
 
-            // Linear blue gradient
-            (1.0 - a) * Vector3::new(1.0, 1.0, 1.0) + a * Vector3::new(0.5, 0.7, 1.0)
+    /// Computes the differential of a ray scattered in a single direction, by reflection or refraction,
+    /// considering that the normal is locally constant.
+    fn specular_differential(
+        &self,
+        hit_record: &HitRecord,
+        scattered_ray: &Ray,
+    ) -> Option<RayDifferential> {
+        let differential = self.differential.as_ref()?;
+        let (dpdx, dpdy) = hit_record.position_derivatives(differential)?;
+
+        let normal = hit_record.normal;
+        let incident = self.direction.normalize();
+        let scattered = scattered_ray.direction().normalize();
+        let cos_incident = -incident.dot(&normal);
+
+        let reflected = scattered.dot(&normal) > 0.0;
+        let cos_scattered = -scattered.dot(&normal);
+
+        // For a refraction, Snell's law gives the ratio of the refractive indices from the tangential components.
+        let incident_tangent = (incident + cos_incident * normal).norm();
+        let eta = match !reflected && incident_tangent > 1e-6 {
+            true => (scattered + cos_scattered * normal).norm() / incident_tangent,
+            false => 1.0,
+        };
+
+        // The change of the scattered direction for a change of the incident direction.
+        let scattered_change = |d_incident: Vector3<f64>| match reflected {
+            true => d_incident - 2.0 * d_incident.dot(&normal) * normal,
+            false => {
+                let d_cos_incident = -d_incident.dot(&normal);
+                let d_mu = (eta - eta * eta * cos_incident / cos_scattered) * d_cos_incident;
+                eta * d_incident + d_mu * normal
+            }
+        };
+
+        let x_change = differential.x_direction.normalize() - incident;
+        let y_change = differential.y_direction.normalize() - incident;
+
+        Some(RayDifferential {
+            x_origin: hit_record.hit_point + dpdx,
+            x_direction: scattered + scattered_change(x_change),
+            y_origin: hit_record.hit_point + dpdy,
+            y_direction: scattered + scattered_change(y_change),
+        })
+    }
+
+    /// Estimates the light coming directly from the background at the given hit,
+    /// by sampling a direction from the background and checking that it is not occluded.
+    fn sample_background(
+        &self,
+        hit_record: &HitRecord,
+        world: &World,
+        rng: &mut dyn RngCore,
+    ) -> Vector3<f64> {
+        let background = world.background();
+        let Some((direction, light_pdf)) = background.sample(rng) else {
+            return Vector3::zeros();
+        };
+
+        let shadow_ray = Ray::new(hit_record.hit_point, direction, self.time);
+        let material = &hit_record.material;
+        let bsdf = material.eval(self, hit_record, &shadow_ray);
+        if bsdf == Vector3::zeros() {
+            return Vector3::zeros();
         }
+
+        if world.hit(
+            &shadow_ray,
+            RealInterval::min_max(0.001, f32::INFINITY),
+            &mut HitRecord::default(),
+            rng,
+        ) {
+            return Vector3::zeros();
+        }
+
+        let weight = power_heuristic(light_pdf, material.pdf(self, hit_record, &shadow_ray));
+        bsdf.component_mul(&background.color(&shadow_ray)) * weight / light_pdf
     }
 }
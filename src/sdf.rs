@@ -0,0 +1,321 @@
+//! Shapes defined by a signed distance function, and functions building and combining such distance functions.
+//!
+//! A signed distance function returns, for any point, the distance to the closest point of the surface,
+//! negative inside the shape. The combinators below take and return distance functions, so that they
+//! can be chained before being given to `SdfShape::new`.
+
+use nalgebra::{Point3, Vector3};
+use rand::RngCore;
+use real_interval::RealInterval;
+use std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::geometry::Hittable;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::utility::{orthonormal_basis, spherical_uv};
+use crate::world::HitRecord;
+
+/// A signed distance function, negative inside the shape.
+pub type DistanceFn = dyn Fn(&Point3<f64>) -> f64 + Send + Sync;
+
+/// The maximum number of steps taken along a ray before giving up.
+const MAX_STEPS: usize = 512;
+/// The distance to the surface under which a point is considered on it.
+const SURFACE_EPSILON: f64 = 1e-4;
+/// The offset used to compute the gradient of the distance function.
+const GRADIENT_EPSILON: f64 = 1e-6;
+
+/// A shape defined by a signed distance function, intersected by sphere tracing: the ray advances
+/// by the distance to the surface until it is close enough to it.
+///
+/// The function must never overestimate the distance to the surface, and the shape must fit in the given bounds.
+/// The normal is the gradient of the function, and the `u` and `v` coordinates are computed from the normal
+/// as on a sphere.
+pub struct SdfShape {
+    distance: Box<DistanceFn>,
+    min: Point3<f64>,
+    max: Point3<f64>,
+    material: Arc<dyn Material>,
+    bbox: AABB,
+}
+
+impl SdfShape {
+    /// Creates a shape from its distance function, and two opposite corners of a box containing it.
+    pub fn new(
+        distance: impl Fn(&Point3<f64>) -> f64 + Send + Sync + 'static,
+        a: Point3<f64>,
+        b: Point3<f64>,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        let min = Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+        let max = Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+
+        Self {
+            distance: Box::new(distance),
+            min,
+            max,
+            material,
+            bbox: AABB::from_points(min, max).pad(),
+        }
+    }
+
+    /// The part of the ray inside the bounds of the shape.
+    fn clip(&self, ray: &Ray, t_interval: RealInterval) -> Option<(f64, f64)> {
+        let mut t_min = t_interval.min as f64;
+        let mut t_max = t_interval.max as f64;
+
+        for axis in 0..3 {
+            let d_invert = 1.0 / ray.direction()[axis];
+            let mut t0 = (self.min[axis] - ray.origin()[axis]) * d_invert;
+            let mut t1 = (self.max[axis] - ray.origin()[axis]) * d_invert;
+            if d_invert < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max < t_min {
+                return None;
+            }
+        }
+
+        Some((t_min, t_max))
+    }
+
+    /// The unit gradient of the distance function, pointing outside of the shape.
+    fn normal(&self, point: &Point3<f64>) -> Vector3<f64> {
+        let gradient = Vector3::from_fn(|axis, _| {
+            let mut offset = Vector3::zeros();
+            offset[axis] = GRADIENT_EPSILON;
+            (self.distance)(&(point + offset)) - (self.distance)(&(point - offset))
+        });
+
+        match gradient.norm_squared() > 0.0 {
+            true => gradient.normalize(),
+            false => Vector3::new(0.0, 1.0, 0.0),
+        }
+    }
+}
+
+impl Hittable for SdfShape {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_interval: RealInterval,
+        hit_record: &mut HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> bool {
+        let Some((t_start, t_end)) = self.clip(ray, t_interval) else {
+            return false;
+        };
+        let direction_norm = ray.direction().norm();
+
+        // The absolute distance is used, so that rays travelling inside the shape also find its surface.
+        // A ray starting on the surface and heading away from it, such as a reflected ray, must first
+        // get away from it. A ray starting on the surface and heading across it hits it right away.
+        let mut t = t_start;
+        let start = ray.origin() + t_start * ray.direction();
+        let start_distance = (self.distance)(&start);
+        let mut left_surface = start_distance.abs() >= SURFACE_EPSILON
+            || start_distance.signum() * self.normal(&start).dot(ray.direction()) < 0.0;
+        let mut hit = None;
+        for _ in 0..MAX_STEPS {
+            if t > t_end {
+                break;
+            }
+
+            let distance = (self.distance)(&(ray.origin() + t * ray.direction())).abs();
+            if distance >= SURFACE_EPSILON {
+                left_surface = true;
+            } else if left_surface {
+                hit = Some(t);
+                break;
+            }
+
+            t += distance.max(SURFACE_EPSILON) / direction_norm;
+        }
+        let Some(t) = hit else {
+            return false;
+        };
+
+        hit_record.t = t;
+        hit_record.hit_point = ray.at(t);
+        hit_record.material = self.material.clone();
+        hit_record.vertex_color = None;
+
+        let outward_normal = self.normal(&hit_record.hit_point);
+        hit_record.set_face_normal(ray, &outward_normal);
+        (hit_record.u, hit_record.v) = spherical_uv(&outward_normal);
+        (hit_record.dpdu, hit_record.dpdv) = orthonormal_basis(&outward_normal);
+
+        true
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        &self.bbox
+    }
+}
+
+/// The distance function of a sphere centered on the origin.
+pub fn sphere(radius: f64) -> impl Fn(&Point3<f64>) -> f64 + Send + Sync + 'static {
+    move |p| p.coords.norm() - radius
+}
+
+/// The distance function of a box centered on the origin, given half the length of its sides.
+pub fn cuboid(half_extents: Vector3<f64>) -> impl Fn(&Point3<f64>) -> f64 + Send + Sync + 'static {
+    move |p| {
+        let q = p.coords.abs() - half_extents;
+        q.sup(&Vector3::zeros()).norm() + q.max().min(0.0)
+    }
+}
+
+/// The distance function of a torus centered on the origin, around the `y` axis.
+pub fn torus(
+    major_radius: f64,
+    minor_radius: f64,
+) -> impl Fn(&Point3<f64>) -> f64 + Send + Sync + 'static {
+    move |p| {
+        let radial = (p.x * p.x + p.z * p.z).sqrt() - major_radius;
+        (radial * radial + p.y * p.y).sqrt() - minor_radius
+    }
+}
+
+/// Moves a shape by the given offset.
+pub fn translate(
+    f: impl Fn(&Point3<f64>) -> f64 + Send + Sync + 'static,
+    offset: Vector3<f64>,
+) -> impl Fn(&Point3<f64>) -> f64 + Send + Sync + 'static {
+    move |p| f(&(p - offset))
+}
+
+/// Merges two shapes, blending them where they are closer than `k` to each other.
+pub fn smooth_union(
+    a: impl Fn(&Point3<f64>) -> f64 + Send + Sync + 'static,
+    b: impl Fn(&Point3<f64>) -> f64 + Send + Sync + 'static,
+    k: f64,
+) -> impl Fn(&Point3<f64>) -> f64 + Send + Sync + 'static {
+    move |p| {
+        let (da, db) = (a(p), b(p));
+        if k <= 0.0 {
+            return da.min(db);
+        }
+
+        // Polynomial smooth minimum.
+        let h = (0.5 + 0.5 * (db - da) / k).clamp(0.0, 1.0);
+        db + (da - db) * h - k * h * (1.0 - h)
+    }
+}
+
+/// Repeats a shape infinitely, with the given period along each axis. A null period disables the repetition
+/// along its axis. The shape must fit in a single period, centered on the origin.
+pub fn repeat(
+    f: impl Fn(&Point3<f64>) -> f64 + Send + Sync + 'static,
+    period: Vector3<f64>,
+) -> impl Fn(&Point3<f64>) -> f64 + Send + Sync + 'static {
+    move |p| {
+        let q = Point3::from(Vector3::from_fn(|axis, _| {
+            let period = period[axis];
+            match period == 0.0 {
+                true => p[axis],
+                false => p[axis] - period * (p[axis] / period).round(),
+            }
+        }));
+        f(&q)
+    }
+}
+
+/// Twists a shape around the `y` axis, by `rate` radians per unit of height.
+pub fn twist(
+    f: impl Fn(&Point3<f64>) -> f64 + Send + Sync + 'static,
+    rate: f64,
+) -> impl Fn(&Point3<f64>) -> f64 + Send + Sync + 'static {
+    move |p| {
+        let (sin, cos) = (rate * p.y).sin_cos();
+        let q = Point3::new(cos * p.x - sin * p.z, p.y, sin * p.x + cos * p.z);
+
+        // The twist stretches space, further from the axis: the distance is scaled down to stay a lower bound.
+        let stretch = (1.0 + (rate * (p.x * p.x + p.z * p.z).sqrt()).powi(2)).sqrt();
+        f(&q) / stretch
+    }
+}
+
+/// Rounds the edges of a shape, by growing it by `radius`.
+pub fn round(
+    f: impl Fn(&Point3<f64>) -> f64 + Send + Sync + 'static,
+    radius: f64,
+) -> impl Fn(&Point3<f64>) -> f64 + Send + Sync + 'static {
+    move |p| f(p) - radius
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn unit_sphere() -> SdfShape {
+        SdfShape::new(
+            sphere(1.0),
+            Point3::new(-1.5, -1.5, -1.5),
+            Point3::new(1.5, 1.5, 1.5),
+            Arc::new(Lambertian::default()),
+        )
+    }
+
+    fn hit(shape: &SdfShape, origin: Point3<f64>, direction: Vector3<f64>) -> Option<HitRecord> {
+        let ray = Ray::new(origin, direction, 0.0);
+        let mut hit_record = HitRecord::default();
+        shape
+            .hit(
+                &ray,
+                RealInterval::min_max(0.001, f32::INFINITY),
+                &mut hit_record,
+                &mut rand::thread_rng(),
+            )
+            .then_some(hit_record)
+    }
+
+    /// The point where the ray along `z` hits the sphere, within `SURFACE_EPSILON` of the surface.
+    fn surface_point(shape: &SdfShape) -> Point3<f64> {
+        hit(shape, Point3::new(0.0, 0.0, -5.0), Vector3::z())
+            .unwrap()
+            .hit_point
+    }
+
+    #[test]
+    fn sphere_hit() {
+        let shape = unit_sphere();
+        let hit_record = hit(&shape, Point3::new(0.0, 0.0, -5.0), Vector3::z()).unwrap();
+
+        assert!((hit_record.t - 4.0).abs() < SURFACE_EPSILON);
+        assert!((hit_record.normal - Vector3::new(0.0, 0.0, -1.0)).norm() < 1e-3);
+        assert!(hit_record.front_face);
+
+        assert!(hit(&shape, Point3::new(0.0, 2.0, -5.0), Vector3::z()).is_none());
+    }
+
+    #[test]
+    fn ray_leaving_surface_does_not_hit_it_again() {
+        let shape = unit_sphere();
+        let point = surface_point(&shape);
+
+        for direction in [
+            Vector3::new(0.0, 0.0, -1.0),
+            Vector3::new(0.3, 0.0, -1.0),
+            Vector3::new(0.0, 1.0, -0.2),
+        ] {
+            assert!(hit(&shape, point, direction).is_none(), "{direction:?}");
+        }
+    }
+
+    #[test]
+    fn ray_entering_surface_hits_far_side() {
+        let shape = unit_sphere();
+        let point = surface_point(&shape);
+
+        let hit_record = hit(&shape, point, Vector3::z()).unwrap();
+        assert!((hit_record.t - 2.0).abs() < 2.0 * SURFACE_EPSILON);
+        assert!((hit_record.normal - Vector3::new(0.0, 0.0, -1.0)).norm() < 1e-3);
+        assert!(!hit_record.front_face);
+    }
+}
@@ -1,46 +1,508 @@
-use nalgebra::{Point3, Vector3};
-use image::{DynamicImage, GenericImageView};
+use image::{DynamicImage, ImageResult};
+use nalgebra::{Point3, Vector2, Vector3};
+use std::path::Path;
+use std::sync::Arc;
 
-#[derive(Clone, Copy)]
+use crate::noise::Perlin;
+use crate::world::HitRecord;
+
+/// A color pattern applied on a surface.
+/// Textures are cheap to clone: nested textures and images are shared using `Arc`.
+#[derive(Clone)]
 pub enum Texture {
     SolidColor(Vector3<f64>),
-    Checker(f64, &'static Texture, &'static Texture),
-    Image(&'static DynamicImage)
+    Checker(f64, Arc<Texture>, Arc<Texture>),
+    Image(Arc<ImageTexture>),
+    /// A texture whose color is modulated by Perlin noise.
+    Noise {
+        noise: Arc<Perlin>,
+        /// The frequency of the noise: features have a size of about `1 / scale`.
+        scale: f64,
+        color: Arc<Texture>,
+    },
+    /// Marble-like veins, following the `z` axis and perturbed by turbulence.
+    Marble {
+        noise: Arc<Perlin>,
+        /// The frequency of the veins.
+        scale: f64,
+        base: Arc<Texture>,
+        veins: Arc<Texture>,
+    },
+    /// Wood-like growth rings around the `y` axis, perturbed by turbulence.
+    Wood {
+        noise: Arc<Perlin>,
+        /// The number of rings per unit of distance.
+        scale: f64,
+        light: Arc<Texture>,
+        dark: Arc<Texture>,
+    },
 }
 
 impl Texture {
+    /// Creates a 3D checker pattern alternating between two textures.
+    /// `inv_scale` is the inverse of the size of a checker cell.
+    pub fn checker(inv_scale: f64, even: Texture, odd: Texture) -> Self {
+        Self::Checker(inv_scale, Arc::new(even), Arc::new(odd))
+    }
+
+    /// Creates a texture modulating `color` by Perlin noise, generated from `seed`.
+    pub fn noise(seed: u64, scale: f64, color: Texture) -> Self {
+        Self::Noise {
+            noise: Arc::new(Perlin::new(seed)),
+            scale,
+            color: Arc::new(color),
+        }
+    }
+
+    /// Creates a marble texture, with `veins` running through `base`, from the noise generated from `seed`.
+    pub fn marble(seed: u64, scale: f64, base: Texture, veins: Texture) -> Self {
+        Self::Marble {
+            noise: Arc::new(Perlin::new(seed)),
+            scale,
+            base: Arc::new(base),
+            veins: Arc::new(veins),
+        }
+    }
+
+    /// Creates a wood texture, alternating `light` and `dark` rings, from the noise generated from `seed`.
+    pub fn wood(seed: u64, scale: f64, light: Texture, dark: Texture) -> Self {
+        Self::Wood {
+            noise: Arc::new(Perlin::new(seed)),
+            scale,
+            light: Arc::new(light),
+            dark: Arc::new(dark),
+        }
+    }
+
+    /// Creates a texture from an image already in memory, with the default settings of `ImageTexture`.
+    pub fn image(image: DynamicImage) -> Self {
+        Self::Image(Arc::new(ImageTexture::new(&image)))
+    }
+
+    /// Loads an image from disk and uses it as a texture.
+    pub fn load_image(path: impl AsRef<Path>) -> ImageResult<Self> {
+        Ok(Self::image(image::open(path)?))
+    }
+
     /// Computes the color of the texture at the given point `p`.
     /// The color also depends on the mapping on the texture, given by the parameters `u` and `v`.
     pub fn value(&self, u: f64, v: f64, p: Point3<f64>) -> Vector3<f64> {
-        match *self {
-            Self::SolidColor(color) => color,
+        self.evaluate(u, v, p, None)
+    }
+
+    /// Computes the color of the texture at a hit point. Image textures are filtered over the area seen
+    /// through the pixel, when the ray carries differentials.
+    pub fn sample(&self, hit_record: &HitRecord) -> Vector3<f64> {
+        let footprint = Some((hit_record.duv_dx, hit_record.duv_dy));
+        self.evaluate(hit_record.u, hit_record.v, hit_record.hit_point, footprint)
+    }
+
+    /// Computes the color of the texture, with the optional derivatives of the texture coordinates
+    /// along the two directions of the image plane.
+    fn evaluate(
+        &self,
+        u: f64,
+        v: f64,
+        p: Point3<f64>,
+        footprint: Option<(Vector2<f64>, Vector2<f64>)>,
+    ) -> Vector3<f64> {
+        match self {
+            Self::SolidColor(color) => *color,
             Self::Checker(inv_scale, color_even, color_odd) => {
                 let x_int = (inv_scale * p.x) as i32;
                 let y_int = (inv_scale * p.y) as i32;
                 let z_int = (inv_scale * p.z) as i32;
 
                 if (x_int + y_int + z_int) % 2 == 0 {
-                    color_even.value(u, v, p)
+                    color_even.evaluate(u, v, p, footprint)
                 } else {
-                    color_odd.value(u, v, p)
+                    color_odd.evaluate(u, v, p, footprint)
                 }
+            }
+            Self::Image(image) => match footprint {
+                Some((duv_dx, duv_dy)) => image.sample(u, v, duv_dx, duv_dy),
+                None => image.value(u, v),
             },
-            Self::Image(image) => {
-                if image.height() == 0 {
-                    // Cyan for debugging purposes
-                    return Vector3::new(0.0, 1.0, 1.0);
+            Self::Noise {
+                noise,
+                scale,
+                color,
+            } => 0.5 * (1.0 + noise.noise(&(*scale * p))) * color.evaluate(u, v, p, footprint),
+            Self::Marble {
+                noise,
+                scale,
+                base,
+                veins,
+            } => {
+                let t = 0.5 * (1.0 + (scale * p.z + 10.0 * noise.turbulence(&p, 7)).sin());
+                let veins = veins.evaluate(u, v, p, footprint);
+                base.evaluate(u, v, p, footprint).lerp(&veins, 1.0 - t)
+            }
+            Self::Wood {
+                noise,
+                scale,
+                light,
+                dark,
+            } => {
+                let distance = (p.x * p.x + p.z * p.z).sqrt() + 0.1 * noise.turbulence(&p, 4);
+                let ring = (scale * distance).fract();
+                // Sharp transitions from the light to the dark wood, then slow growth back to light wood.
+                let t = ring.powi(3);
+                let dark = dark.evaluate(u, v, p, footprint);
+                light.evaluate(u, v, p, footprint).lerp(&dark, t)
+            }
+        }
+    }
+}
+
+/// How texture coordinates outside of `[0, 1]` are mapped on the image.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WrapMode {
+    /// The image is tiled.
+    #[default]
+    Repeat,
+    /// The image is tiled, every other tile being mirrored, so that there are no seams between the tiles.
+    Mirror,
+    /// The pixels on the edges of the image are extended.
+    Clamp,
+}
+
+impl WrapMode {
+    /// Maps an integer pixel coordinate in the image of the given size.
+    fn wrap(&self, coordinate: i64, size: usize) -> usize {
+        let size = size as i64;
+        let wrapped = match self {
+            WrapMode::Repeat => coordinate.rem_euclid(size),
+            WrapMode::Mirror => {
+                let period = coordinate.rem_euclid(2 * size);
+                match period < size {
+                    true => period,
+                    false => 2 * size - 1 - period,
                 }
+            }
+            WrapMode::Clamp => coordinate.clamp(0, size - 1),
+        };
+
+        wrapped as usize
+    }
+}
+
+/// How the color between the centers of the pixels is computed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    /// The color of the closest pixel, giving a blocky look when the image is magnified.
+    Nearest,
+    /// The colors of the four closest pixels, linearly interpolated.
+    #[default]
+    Bilinear,
+}
+
+/// An image used as a texture, with its wrap mode, filter and texture coordinates transformation.
+/// The colors are stored in linear space: 8 and 16 bits images are assumed to be encoded in sRGB,
+/// and are decoded when the texture is created.
+/// The image is stored with a pyramid of downsampled versions (a mipmap), used to filter the texture
+/// when it covers a large area of the image seen through a pixel.
+pub struct ImageTexture {
+    /// The levels of the mipmap, starting from the full resolution image, each one half the size of the previous.
+    levels: Vec<MipLevel>,
+    wrap_mode: WrapMode,
+    filter: Filter,
+    /// The scaling, rotation (in radians) and offset applied to the texture coordinates, in that order.
+    uv_scale: (f64, f64),
+    uv_rotation: f64,
+    uv_offset: (f64, f64),
+}
+
+impl ImageTexture {
+    /// Creates a texture from an image, decoding 8 and 16 bits images from sRGB.
+    /// Floating point images, such as `.hdr` or `.exr` files, are assumed to be linear already.
+    pub fn new(image: &DynamicImage) -> Self {
+        let srgb = !matches!(
+            image,
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+        );
+        Self::from_image(image, srgb)
+    }
+
+    /// Creates a texture from an image whose values are not colors, such as a normal map,
+    /// and must be used without any decoding.
+    pub fn linear(image: &DynamicImage) -> Self {
+        Self::from_image(image, false)
+    }
+
+    fn from_image(image: &DynamicImage, srgb: bool) -> Self {
+        let image = image.to_rgb32f();
+        let decode = |value: f32| match srgb {
+            true => srgb_to_linear(value),
+            false => value,
+        };
+
+        let mut levels = vec![MipLevel {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            pixels: image
+                .pixels()
+                .map(|p| Vector3::new(decode(p[0]), decode(p[1]), decode(p[2])))
+                .collect(),
+        }];
+        while let Some(level) = levels.last().and_then(MipLevel::downsample) {
+            levels.push(level);
+        }
+
+        Self {
+            levels,
+            wrap_mode: WrapMode::default(),
+            filter: Filter::default(),
+            uv_scale: (1.0, 1.0),
+            uv_rotation: 0.0,
+            uv_offset: (0.0, 0.0),
+        }
+    }
+
+    /// Sets how the texture coordinates outside of `[0, 1]` are handled, `WrapMode::Repeat` by default.
+    pub fn set_wrap_mode(&mut self, wrap_mode: WrapMode) {
+        self.wrap_mode = wrap_mode;
+    }
+
+    /// Sets how the image is interpolated, `Filter::Bilinear` by default.
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+    }
 
-                let u = u.clamp(0.0, 1.0);
-                let v = 1.0 - v.clamp(0.0, 1.0);
+    /// Sets the transformation of the texture coordinates before the lookup: they are scaled,
+    /// then rotated around the origin by `rotation` radians, then offset.
+    /// For instance, a scale of `(4.0, 4.0)` repeats the image four times in each direction.
+    pub fn set_uv_transform(&mut self, scale: (f64, f64), rotation: f64, offset: (f64, f64)) {
+        self.uv_scale = scale;
+        self.uv_rotation = rotation;
+        self.uv_offset = offset;
+    }
+
+    /// The linear color of the texture at the given texture coordinates, from the full resolution image.
+    /// `v` goes from the bottom to the top of the image.
+    pub fn value(&self, u: f64, v: f64) -> Vector3<f64> {
+        if self.is_empty() {
+            // Cyan for debugging purposes
+            return Vector3::new(0.0, 1.0, 1.0);
+        }
+
+        let (u, v) = self.transform_uv(u, v);
+        self.lookup(&self.levels[0], u, v)
+    }
+
+    /// The linear color of the texture averaged over a footprint, given by the derivatives of the texture
+    /// coordinates along the two directions of the image plane.
+    /// The two levels of the mipmap whose pixels best match the size of the footprint are interpolated.
+    pub fn sample(
+        &self,
+        u: f64,
+        v: f64,
+        duv_dx: Vector2<f64>,
+        duv_dy: Vector2<f64>,
+    ) -> Vector3<f64> {
+        if self.is_empty() {
+            // Cyan for debugging purposes
+            return Vector3::new(0.0, 1.0, 1.0);
+        }
+
+        // The width of the footprint, in pixels of the full resolution image.
+        let base = &self.levels[0];
+        let footprint = [duv_dx, duv_dy]
+            .iter()
+            .map(|derivative| {
+                let (du, dv) = self.transform_derivative(derivative);
+                (du * base.width as f64).hypot(dv * base.height as f64)
+            })
+            .fold(0.0, f64::max);
+
+        let (u, v) = self.transform_uv(u, v);
+        if !footprint.is_finite() || footprint <= 1.0 {
+            return self.lookup(base, u, v);
+        }
+
+        let level = footprint.log2().min((self.levels.len() - 1) as f64);
+        let lower = level.floor() as usize;
+        let upper = (lower + 1).min(self.levels.len() - 1);
+
+        let color = self.lookup(&self.levels[lower], u, v);
+        match upper == lower {
+            true => color,
+            false => color.lerp(&self.lookup(&self.levels[upper], u, v), level.fract()),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.levels[0].width == 0 || self.levels[0].height == 0
+    }
+
+    fn transform_uv(&self, u: f64, v: f64) -> (f64, f64) {
+        let (du, dv) = self.transform_derivative(&Vector2::new(u, v));
+        (du + self.uv_offset.0, dv + self.uv_offset.1)
+    }
+
+    /// Applies the scaling and the rotation of the texture coordinates transformation, without the offset.
+    fn transform_derivative(&self, derivative: &Vector2<f64>) -> (f64, f64) {
+        let (u, v) = (
+            derivative.x * self.uv_scale.0,
+            derivative.y * self.uv_scale.1,
+        );
+        let (sin, cos) = self.uv_rotation.sin_cos();
+        (cos * u - sin * v, sin * u + cos * v)
+    }
 
-                let x = (u * image.width() as f64) as u32;
-                let y = (v * image.height() as f64) as u32;
-                let pixel = image.get_pixel(x, y);
+    /// The color of a level of the mipmap at the given transformed texture coordinates, using the filter.
+    fn lookup(&self, level: &MipLevel, u: f64, v: f64) -> Vector3<f64> {
+        let x = u * level.width as f64;
+        let y = (1.0 - v) * level.height as f64;
 
-                let color_scale = 1.0 / 255.0;
-                Vector3::new(pixel[0] as f64 * color_scale, pixel[1] as f64 * color_scale, pixel[2] as f64 * color_scale)
+        match self.filter {
+            Filter::Nearest => self.pixel(level, x.floor() as i64, y.floor() as i64),
+            Filter::Bilinear => {
+                // The coordinates relative to the centers of the pixels.
+                let x = x - 0.5;
+                let y = y - 0.5;
+                let (x0, y0) = (x.floor(), y.floor());
+                let (tx, ty) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+
+                let top = self
+                    .pixel(level, x0, y0)
+                    .lerp(&self.pixel(level, x0 + 1, y0), tx);
+                let bottom = self
+                    .pixel(level, x0, y0 + 1)
+                    .lerp(&self.pixel(level, x0 + 1, y0 + 1), tx);
+                top.lerp(&bottom, ty)
             }
         }
     }
+
+    /// The color of a pixel of a level of the mipmap, whose coordinates are wrapped in the image.
+    fn pixel(&self, level: &MipLevel, x: i64, y: i64) -> Vector3<f64> {
+        let x = self.wrap_mode.wrap(x, level.width);
+        let y = self.wrap_mode.wrap(y, level.height);
+        level.pixels[y * level.width + x].cast()
+    }
+}
+
+/// A level of the mipmap of an `ImageTexture`.
+struct MipLevel {
+    width: usize,
+    height: usize,
+    /// The linear colors of the pixels, row by row, starting from the top of the image.
+    pixels: Vec<Vector3<f32>>,
+}
+
+impl MipLevel {
+    /// The next level of the mipmap, half the size of this one, each pixel averaging a block of 2 by 2 pixels.
+    /// Returns `None` once the level is a single pixel.
+    fn downsample(&self) -> Option<Self> {
+        if self.width * self.height <= 1 {
+            return None;
+        }
+
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let (x0, y0) = (2 * x, 2 * y);
+                let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+                (self.pixels[y0 * self.width + x0]
+                    + self.pixels[y0 * self.width + x1]
+                    + self.pixels[y1 * self.width + x0]
+                    + self.pixels[y1 * self.width + x1])
+                    / 4.0
+            })
+            .collect();
+
+        Some(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+}
+
+/// Decodes an sRGB encoded value in `[0, 1]` to linear space.
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, Rgb32FImage, RgbImage};
+
+    /// A 2 by 2 linear texture, whose pixels are 0 and 1 on the top row, 2 and 3 on the bottom row.
+    fn texture(wrap_mode: WrapMode, filter: Filter) -> ImageTexture {
+        let image = Rgb32FImage::from_fn(2, 2, |x, y| Rgb([(2 * y + x) as f32; 3]));
+        let mut texture = ImageTexture::new(&DynamicImage::ImageRgb32F(image));
+        texture.set_wrap_mode(wrap_mode);
+        texture.set_filter(filter);
+        texture
+    }
+
+    fn assert_value(color: Vector3<f64>, expected: f64) {
+        assert!(
+            (color - Vector3::repeat(expected)).norm() < 1e-6,
+            "{color:?} != {expected}"
+        );
+    }
+
+    #[test]
+    fn wrap_modes() {
+        // On the top row, at the coordinates u = -0.75, -0.25 and 1.25.
+        let expected = [
+            (WrapMode::Repeat, [0.0, 1.0, 0.0]),
+            (WrapMode::Clamp, [0.0, 0.0, 1.0]),
+            (WrapMode::Mirror, [1.0, 0.0, 1.0]),
+        ];
+        for (wrap_mode, values) in expected {
+            let texture = texture(wrap_mode, Filter::Nearest);
+            for (u, value) in [-0.75, -0.25, 1.25].into_iter().zip(values) {
+                assert_value(texture.value(u, 0.75), value);
+            }
+        }
+    }
+
+    #[test]
+    fn bilinear_filtering() {
+        let texture = texture(WrapMode::Clamp, Filter::Bilinear);
+        // At the centers of the pixels.
+        assert_value(texture.value(0.25, 0.75), 0.0);
+        assert_value(texture.value(0.75, 0.25), 3.0);
+        // Halfway between the two pixels of the top row, and between the four pixels.
+        assert_value(texture.value(0.5, 0.75), 0.5);
+        assert_value(texture.value(0.5, 0.5), 1.5);
+    }
+
+    #[test]
+    fn srgb_decoding() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(1, 1, Rgb([128; 3])));
+        let encoded = 128.0 / 255.0;
+        assert_value(ImageTexture::new(&image).value(0.5, 0.5), 0.2158605);
+        assert_value(ImageTexture::linear(&image).value(0.5, 0.5), encoded);
+    }
+
+    #[test]
+    fn mip_level_selection() {
+        let texture = texture(WrapMode::Repeat, Filter::Nearest);
+        let sample = |footprint: f64| {
+            let duv_dx = Vector2::new(footprint / 2.0, 0.0);
+            texture.sample(0.25, 0.75, duv_dx, Vector2::zeros())
+        };
+
+        // Within a pixel, the full resolution image is used.
+        assert_value(sample(0.0), 0.0);
+        assert_value(sample(1.0), 0.0);
+        // Two pixels wide, the single pixel level averages the whole image.
+        assert_value(sample(2.0), 1.5);
+        assert_value(sample(8.0), 1.5);
+        // In between, the two levels are interpolated.
+        assert_value(sample(2.0_f64.sqrt()), 0.75);
+    }
 }
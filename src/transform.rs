@@ -0,0 +1,205 @@
+use nalgebra::{Matrix3, Matrix4, Point3};
+use rand::RngCore;
+use real_interval::RealInterval;
+
+use crate::aabb::AABB;
+use crate::geometry::{Hittable, Span};
+use crate::ray::Ray;
+use crate::world::HitRecord;
+
+/// A hittable placed in the world using an affine transformation, such as a translation, a rotation or a scaling.
+/// Wrap the object in an `Arc` to instance it several times with different transformations.
+///
+/// The matrix can be built with `nalgebra`, for instance with `Matrix4::new_translation`,
+/// `Matrix4::from_axis_angle` or `Matrix4::new_nonuniform_scaling`, and composed by multiplication.
+pub struct Transformed<H> {
+    object: H,
+    /// The transformation from object space to world space.
+    matrix: Matrix4<f64>,
+    /// The transformation from world space to object space.
+    inverse: Matrix4<f64>,
+    /// The transformation of the normals from object space to world space.
+    normal_matrix: Matrix3<f64>,
+    bbox: AABB,
+}
+
+impl<H: Hittable> Transformed<H> {
+    pub fn new(object: H, matrix: Matrix4<f64>) -> Self {
+        let inverse = matrix
+            .try_inverse()
+            .expect("the transformation matrix is not invertible");
+        let normal_matrix = inverse.fixed_view::<3, 3>(0, 0).transpose();
+
+        // The world-space box contains the eight transformed corners of the object-space box.
+        // An unbounded object stays unbounded once transformed.
+        if !object.is_bounded() {
+            return Self {
+                object,
+                matrix,
+                inverse,
+                normal_matrix,
+                bbox: AABB::infinite(),
+            };
+        }
+        let object_bbox = object.bounding_box();
+        let bound = |axis: usize, max: bool| match max {
+            true => object_bbox.axis(axis).max as f64,
+            false => object_bbox.axis(axis).min as f64,
+        };
+
+        let mut bbox: Option<AABB> = None;
+        for corner in 0..8 {
+            let point = Point3::new(
+                bound(0, corner & 1 == 1),
+                bound(1, corner & 2 == 2),
+                bound(2, corner & 4 == 4),
+            );
+            let point = matrix.transform_point(&point);
+            let corner_box = AABB::from_points(point, point);
+
+            bbox = Some(match bbox {
+                Some(bbox) => AABB::from_boxes(&bbox, &corner_box),
+                None => corner_box,
+            });
+        }
+
+        Self {
+            object,
+            matrix,
+            inverse,
+            normal_matrix,
+            bbox: bbox.unwrap(),
+        }
+    }
+}
+
+impl<H: Hittable> Hittable for Transformed<H> {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_interval: RealInterval,
+        hit_record: &mut HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> bool {
+        // The direction is not normalised, so that `t` is the same in both spaces.
+        let object_ray = Ray::new(
+            self.inverse.transform_point(ray.origin()),
+            self.inverse.transform_vector(ray.direction()),
+            ray.time(),
+        );
+
+        if !self.object.hit(&object_ray, t_interval, hit_record, rng) {
+            return false;
+        }
+
+        // The side of the surface hit by the ray does not change with the transformation.
+        hit_record.hit_point = self.matrix.transform_point(&hit_record.hit_point);
+        hit_record.normal = (self.normal_matrix * hit_record.normal).normalize();
+        hit_record.dpdu = self.matrix.transform_vector(&hit_record.dpdu);
+        hit_record.dpdv = self.matrix.transform_vector(&hit_record.dpdv);
+
+        true
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        &self.bbox
+    }
+
+    fn is_bounded(&self) -> bool {
+        self.object.is_bounded()
+    }
+
+    fn is_solid(&self) -> bool {
+        self.object.is_solid()
+    }
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        let object_ray = Ray::new(
+            self.inverse.transform_point(ray.origin()),
+            self.inverse.transform_vector(ray.direction()),
+            ray.time(),
+        );
+
+        let mut spans = self.object.spans(&object_ray)?;
+        for span in spans.iter_mut() {
+            for crossing in [&mut span.enter, &mut span.exit] {
+                crossing.outward_normal =
+                    (self.normal_matrix * crossing.outward_normal).normalize();
+                crossing.dpdu = self.matrix.transform_vector(&crossing.dpdu);
+                crossing.dpdv = self.matrix.transform_vector(&crossing.dpdv);
+            }
+        }
+
+        Some(spans)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{BoxShape, Sphere};
+    use crate::material::Lambertian;
+    use nalgebra::Vector3;
+    use std::f64::consts::FRAC_PI_4;
+    use std::sync::Arc;
+
+    #[test]
+    fn rotated_box_bounds() {
+        let cube = BoxShape::new(
+            Point3::new(-1.0, -1.0, -1.0),
+            Point3::new(1.0, 1.0, 1.0),
+            Arc::new(Lambertian::default()),
+        );
+        let matrix = Matrix4::new_translation(&Vector3::new(0.0, 0.0, 3.0))
+            * Matrix4::from_axis_angle(&Vector3::z_axis(), FRAC_PI_4);
+        let transformed = Transformed::new(cube, matrix);
+
+        // The corners of the cube are rotated onto the axes, at a distance of `sqrt(2)`.
+        let bbox = transformed.bounding_box();
+        let half_diagonal = 2.0_f64.sqrt();
+        for (axis, (min, max)) in [
+            (-half_diagonal, half_diagonal),
+            (-half_diagonal, half_diagonal),
+            (2.0, 4.0),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let interval = bbox.axis(axis);
+            assert!(
+                (interval.min as f64 - min).abs() < 1e-5,
+                "{axis}: {interval:?}"
+            );
+            assert!(
+                (interval.max as f64 - max).abs() < 1e-5,
+                "{axis}: {interval:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn normals_under_nonuniform_scaling() {
+        // A unit sphere stretched along `x`, into the ellipsoid `x² / 4 + y² + z² = 1`.
+        let sphere = Sphere::stationary(Point3::origin(), 1.0, Arc::new(Lambertian::default()));
+        let matrix = Matrix4::new_nonuniform_scaling(&Vector3::new(2.0, 1.0, 1.0));
+        let ellipsoid = Transformed::new(sphere, matrix);
+
+        // The normal of the ellipsoid is the gradient `(x / 4, y, z)`, not the scaled sphere normal `(x / 2, y, z)`.
+        let point = Point3::new(2.0_f64.sqrt(), 0.5_f64.sqrt(), 0.0);
+        let normal = Vector3::new(1.0, 2.0, 0.0).normalize();
+
+        // A ray along the normal hits the convex ellipsoid at the point first.
+        let ray = Ray::new(point + 3.0 * normal, -normal, 0.0);
+        let mut hit_record = HitRecord::default();
+        assert!(ellipsoid.hit(
+            &ray,
+            RealInterval::min_max(0.001, f32::MAX),
+            &mut hit_record,
+            &mut rand::thread_rng(),
+        ));
+        assert!((hit_record.t - 3.0).abs() < 1e-9);
+        assert!((hit_record.hit_point - point).norm() < 1e-9);
+        assert!((hit_record.normal - normal).norm() < 1e-9);
+        assert!(hit_record.front_face);
+    }
+}
@@ -66,6 +66,30 @@ pub fn reflectance(cosine: f64, indices_ratio: f64) -> f64 {
     r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
 }
 
+/// Weights a sample of a strategy with density `pdf`, combined with another strategy with density `other_pdf`,
+/// using Veach's power heuristic for multiple importance sampling.
+pub fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let pdf2 = pdf * pdf;
+    let other_pdf2 = other_pdf * other_pdf;
+
+    if pdf2 + other_pdf2 == 0.0 {
+        return 0.0;
+    }
+    pdf2 / (pdf2 + other_pdf2)
+}
+
+/// Computes the `(u, v)` coordinates of a point of the unit sphere, given as a unit vector.
+/// `u` goes around the `y` axis starting from `-x`, and `v` goes from `-y` to `+y`.
+pub fn spherical_uv(point: &Vector3<f64>) -> (f64, f64) {
+    let theta = f64::acos((-point.y).clamp(-1.0, 1.0));
+    let phi = f64::atan2(-point.z, point.x) + std::f64::consts::PI;
+
+    (
+        phi / (2.0 * std::f64::consts::PI),
+        theta / std::f64::consts::PI,
+    )
+}
+
 /// A 2-dimensional basis, containing 2 base vectors, `u` and `v`.
 pub struct Basis2<T> {
     pub u: Vector3<T>,
@@ -90,3 +114,215 @@ impl<T> Basis3<T> {
         Self { u, v, w }
     }
 }
+
+impl Basis3<f64> {
+    /// The coordinates in this basis, assumed orthonormal, of a vector given in world space.
+    pub fn to_local(&self, vector: &Vector3<f64>) -> Vector3<f64> {
+        Vector3::new(
+            vector.dot(&self.u),
+            vector.dot(&self.v),
+            vector.dot(&self.w),
+        )
+    }
+
+    /// The vector in world space with the given coordinates in this basis.
+    pub fn to_world(&self, vector: &Vector3<f64>) -> Vector3<f64> {
+        vector.x * self.u + vector.y * self.v + vector.z * self.w
+    }
+}
+
+/// Builds two unit vectors forming an orthonormal basis with the given unit `normal`.
+pub fn orthonormal_basis(normal: &Vector3<f64>) -> (Vector3<f64>, Vector3<f64>) {
+    let helper = if normal.x.abs() > 0.9 {
+        Vector3::new(0.0, 1.0, 0.0)
+    } else {
+        Vector3::new(1.0, 0.0, 0.0)
+    };
+    let tangent = normal.cross(&helper).normalize();
+    let bitangent = normal.cross(&tangent);
+
+    (tangent, bitangent)
+}
+
+/// Tolerance used to detect null coefficients when solving polynomials.
+const POLYNOMIAL_EPSILON: f64 = 1e-9;
+
+/// Returns the real roots of `a x² + b x + c`, in increasing order.
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a.abs() < POLYNOMIAL_EPSILON {
+        if b.abs() < POLYNOMIAL_EPSILON {
+            return vec![];
+        }
+        return vec![-c / b];
+    }
+
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return vec![];
+    }
+
+    // Avoids the cancellation of `-b + sqrt(discriminant)` when `b` is large.
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let mut roots = match q == 0.0 {
+        true => vec![0.0],
+        false => vec![q / a, c / q],
+    };
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+/// Returns the real roots of `x³ + a x² + b x + c`, using Cardano's method.
+fn solve_normalized_cubic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    // Substitute x = y - a/3 to eliminate the quadratic term: y³ + 3 p y + 2 q = 0
+    let sq_a = a * a;
+    let p = (-sq_a / 3.0 + b) / 3.0;
+    let q = (2.0 / 27.0 * a * sq_a - a * b / 3.0 + c) / 2.0;
+
+    let cb_p = p * p * p;
+    let discriminant = q * q + cb_p;
+
+    let roots = if discriminant.abs() < POLYNOMIAL_EPSILON {
+        if q.abs() < POLYNOMIAL_EPSILON {
+            vec![0.0]
+        } else {
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    } else if discriminant < 0.0 {
+        // Three real roots
+        let phi = (-q / (-cb_p).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+        let t = 2.0 * (-p).sqrt();
+        vec![
+            t * phi.cos(),
+            -t * (phi + std::f64::consts::PI / 3.0).cos(),
+            -t * (phi - std::f64::consts::PI / 3.0).cos(),
+        ]
+    } else {
+        let sqrt_discriminant = discriminant.sqrt();
+        vec![(sqrt_discriminant - q).cbrt() - (sqrt_discriminant + q).cbrt()]
+    };
+
+    roots.into_iter().map(|y| y - a / 3.0).collect()
+}
+
+/// Returns the real roots of `c4 x⁴ + c3 x³ + c2 x² + c1 x + c0`, in increasing order,
+/// using Ferrari's method followed by a few Newton iterations to refine the roots.
+pub fn solve_quartic(c4: f64, c3: f64, c2: f64, c1: f64, c0: f64) -> Vec<f64> {
+    let (a, b, c, d) = (c3 / c4, c2 / c4, c1 / c4, c0 / c4);
+
+    // Substitute x = y - a/4 to eliminate the cubic term: y⁴ + p y² + q y + r = 0
+    let sq_a = a * a;
+    let p = -3.0 / 8.0 * sq_a + b;
+    let q = sq_a * a / 8.0 - a * b / 2.0 + c;
+    let r = -3.0 / 256.0 * sq_a * sq_a + sq_a * b / 16.0 - a * c / 4.0 + d;
+
+    let mut roots = if r.abs() < POLYNOMIAL_EPSILON {
+        // No constant term: y (y³ + p y + q) = 0
+        let mut roots = solve_normalized_cubic(0.0, p, q);
+        roots.push(0.0);
+        roots
+    } else {
+        // Solve the resolvent cubic, and use one of its roots to split the quartic into two quadratics.
+        let z = solve_normalized_cubic(-p / 2.0, -r, r * p / 2.0 - q * q / 8.0)[0];
+
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+        if u < -POLYNOMIAL_EPSILON || v < -POLYNOMIAL_EPSILON {
+            return vec![];
+        }
+        let u = u.max(0.0).sqrt();
+        let v = match q < 0.0 {
+            true => -v.max(0.0).sqrt(),
+            false => v.max(0.0).sqrt(),
+        };
+
+        let mut roots = solve_quadratic(1.0, v, z - u);
+        roots.extend(solve_quadratic(1.0, -v, z + u));
+        roots
+    };
+
+    let polynomial = |x: f64| (((c4 * x + c3) * x + c2) * x + c1) * x + c0;
+    let derivative = |x: f64| ((4.0 * c4 * x + 3.0 * c3) * x + 2.0 * c2) * x + c1;
+
+    for root in roots.iter_mut() {
+        *root -= a / 4.0;
+
+        for _ in 0..2 {
+            let slope = derivative(*root);
+            if slope.abs() > POLYNOMIAL_EPSILON {
+                *root -= polynomial(*root) / slope;
+            }
+        }
+    }
+
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_roots(roots: &[f64], expected: &[f64]) {
+        assert_eq!(roots.len(), expected.len(), "{roots:?} != {expected:?}");
+        for (root, expected) in roots.iter().zip(expected) {
+            assert!((root - expected).abs() < 1e-6, "{roots:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn quadratic() {
+        // (x + 1) (x - 3)
+        assert_roots(&solve_quadratic(1.0, -2.0, -3.0), &[-1.0, 3.0]);
+        // 2 (x - 0.5) (x - 4)
+        assert_roots(&solve_quadratic(2.0, -9.0, 4.0), &[0.5, 4.0]);
+        // Linear: 2 x - 1
+        assert_roots(&solve_quadratic(0.0, 2.0, -1.0), &[0.5]);
+    }
+
+    #[test]
+    fn quadratic_double_root() {
+        // (x - 1)²
+        assert_roots(&solve_quadratic(1.0, -2.0, 1.0), &[1.0, 1.0]);
+        assert_roots(&solve_quadratic(1.0, 0.0, 0.0), &[0.0]);
+    }
+
+    #[test]
+    fn quadratic_no_real_root() {
+        assert!(solve_quadratic(1.0, 0.0, 1.0).is_empty());
+        assert!(solve_quadratic(0.0, 0.0, 1.0).is_empty());
+    }
+
+    #[test]
+    fn quartic() {
+        // (x - 1) (x - 2) (x - 3) (x - 4)
+        assert_roots(
+            &solve_quartic(1.0, -10.0, 35.0, -50.0, 24.0),
+            &[1.0, 2.0, 3.0, 4.0],
+        );
+        // (x² - 1) (x² - 4), without odd terms like the torus seen along its axis
+        assert_roots(
+            &solve_quartic(1.0, 0.0, -5.0, 0.0, 4.0),
+            &[-2.0, -1.0, 1.0, 2.0],
+        );
+        // 2 (x + 1) (x - 0.5) (x² + 1), with two complex roots
+        assert_roots(&solve_quartic(2.0, 1.0, 1.0, 1.0, -1.0), &[-1.0, 0.5]);
+    }
+
+    #[test]
+    fn quartic_double_root() {
+        // (x - 1)² (x + 2)²
+        let roots = solve_quartic(1.0, 2.0, -3.0, -4.0, 4.0);
+        assert!(!roots.is_empty());
+        assert!(roots
+            .iter()
+            .all(|root| (root - 1.0).abs() < 1e-4 || (root + 2.0).abs() < 1e-4));
+    }
+
+    #[test]
+    fn quartic_no_real_root() {
+        assert!(solve_quartic(1.0, 0.0, 0.0, 0.0, 1.0).is_empty());
+        // (x² + 1) (x² + 2 x + 5)
+        assert!(solve_quartic(1.0, 2.0, 6.0, 2.0, 5.0).is_empty());
+    }
+}
@@ -0,0 +1,472 @@
+use nalgebra::{Point3, Vector3};
+use rand::{Rng, RngCore};
+use real_interval::RealInterval;
+use std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::geometry::{BoxShape, Hittable};
+use crate::material::{Isotropic, Material};
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::world::HitRecord;
+
+/// A participating medium of constant density, such as smoke or fog, filling a closed `boundary`.
+/// A ray travelling through the medium is scattered at a distance following an exponential distribution,
+/// in a direction chosen by the isotropic phase function.
+pub struct ConstantMedium<H> {
+    boundary: H,
+    /// The probability of scattering per unit of distance.
+    density: f64,
+    phase_function: Arc<dyn Material>,
+}
+
+impl<H: Hittable> ConstantMedium<H> {
+    /// Creates a medium scattering the light with the given color.
+    pub fn new(boundary: H, density: f64, albedo: Texture) -> Self {
+        Self::with_phase_function(boundary, density, Arc::new(Isotropic::new(albedo)))
+    }
+
+    /// Creates a medium scattering the light with a custom phase function.
+    pub fn with_phase_function(
+        boundary: H,
+        density: f64,
+        phase_function: Arc<dyn Material>,
+    ) -> Self {
+        assert!(density > 0.0, "the density of a medium must be positive");
+
+        Self {
+            boundary,
+            density,
+            phase_function,
+        }
+    }
+}
+
+impl<H: Hittable> Hittable for ConstantMedium<H> {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_interval: RealInterval,
+        hit_record: &mut HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> bool {
+        let Some((t_enter, t_exit)) = boundary_segment(&self.boundary, ray, t_interval, rng) else {
+            return false;
+        };
+
+        let direction_norm = ray.direction().norm();
+        let distance_inside = (t_exit - t_enter) * direction_norm;
+        let hit_distance = -(1.0 - rng.gen::<f64>()).ln() / self.density;
+        if hit_distance > distance_inside {
+            return false;
+        }
+
+        let t = t_enter + hit_distance / direction_norm;
+        if t <= t_interval.min as f64 || t_interval.max as f64 <= t {
+            return false;
+        }
+
+        record_scattering(ray, t, &self.phase_function, hit_record);
+        true
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        self.boundary.bounding_box()
+    }
+
+    fn is_bounded(&self) -> bool {
+        self.boundary.is_bounded()
+    }
+}
+
+/// Finds the part of the ray, within the interval, inside a closed boundary.
+/// The ray may start inside the boundary, for instance after being scattered by the medium.
+fn boundary_segment(
+    boundary: &impl Hittable,
+    ray: &Ray,
+    t_interval: RealInterval,
+    rng: &mut dyn RngCore,
+) -> Option<(f64, f64)> {
+    let mut first = HitRecord::default();
+    if !boundary.hit(
+        ray,
+        RealInterval::min_max(t_interval.min, f32::INFINITY),
+        &mut first,
+        rng,
+    ) {
+        return None;
+    }
+
+    // Hitting the back of the boundary first means the ray starts inside of it.
+    if !first.front_face {
+        return Some((t_interval.min as f64, first.t.min(t_interval.max as f64)));
+    }
+
+    if first.t >= t_interval.max as f64 {
+        return None;
+    }
+
+    let mut second = HitRecord::default();
+    if !boundary.hit(
+        ray,
+        RealInterval::min_max(first.t as f32 + 0.0001, f32::INFINITY),
+        &mut second,
+        rng,
+    ) {
+        return None;
+    }
+
+    Some((first.t, second.t.min(t_interval.max as f64)))
+}
+
+/// Fills the hit record for a ray scattered inside a medium. The normal and the face are arbitrary.
+fn record_scattering(
+    ray: &Ray,
+    t: f64,
+    phase_function: &Arc<dyn Material>,
+    hit_record: &mut HitRecord,
+) {
+    hit_record.t = t;
+    hit_record.hit_point = ray.at(t);
+    hit_record.normal = Vector3::new(1.0, 0.0, 0.0);
+    hit_record.front_face = true;
+    hit_record.u = 0.0;
+    hit_record.v = 0.0;
+    hit_record.dpdu = Vector3::zeros();
+    hit_record.dpdv = Vector3::zeros();
+    hit_record.material = phase_function.clone();
+    hit_record.vertex_color = None;
+}
+
+/// The density of a heterogeneous medium, varying in space.
+pub trait Density: Send + Sync {
+    /// The probability of scattering per unit of distance at the given point.
+    fn density(&self, point: &Point3<f64>) -> f64;
+
+    /// An upper bound of the density over the whole medium, used to sample the scattering distances.
+    fn majorant(&self) -> f64;
+}
+
+/// A density given by a grid of voxels, linearly interpolated between the voxels centers.
+/// The density is null outside of the grid.
+pub struct VoxelGrid {
+    resolution: [usize; 3],
+    /// The values of the voxels, `x` varying first, then `y`, then `z`.
+    values: Vec<f32>,
+    min: Point3<f64>,
+    max: Point3<f64>,
+    /// The factor applied to the values of the voxels.
+    scale: f64,
+    /// The largest value of the voxels.
+    max_value: f64,
+}
+
+impl VoxelGrid {
+    /// Creates a grid filling the box between two opposite corners.
+    /// `values` are ordered with `x` varying first, then `y`, then `z`.
+    pub fn new(resolution: [usize; 3], values: Vec<f32>, a: Point3<f64>, b: Point3<f64>) -> Self {
+        assert!(resolution.iter().all(|&n| n > 0));
+        assert_eq!(values.len(), resolution.iter().product::<usize>());
+
+        let max_value = values
+            .iter()
+            .fold(0.0, |max, &value| f64::max(max, value as f64));
+
+        Self {
+            resolution,
+            values,
+            min: Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+            scale: 1.0,
+            max_value,
+        }
+    }
+
+    /// Sets the factor applied to the values of the voxels, `1.0` by default.
+    pub fn set_scale(&mut self, scale: f64) {
+        assert!(scale >= 0.0);
+        self.scale = scale;
+    }
+
+    /// The two opposite corners of the box filled by the grid.
+    pub fn bounds(&self) -> (Point3<f64>, Point3<f64>) {
+        (self.min, self.max)
+    }
+
+    fn value(&self, x: usize, y: usize, z: usize) -> f64 {
+        let [nx, ny, _] = self.resolution;
+        self.values[(z * ny + y) * nx + x] as f64
+    }
+}
+
+impl Density for VoxelGrid {
+    fn density(&self, point: &Point3<f64>) -> f64 {
+        if (0..3).any(|axis| point[axis] < self.min[axis] || self.max[axis] < point[axis]) {
+            return 0.0;
+        }
+
+        // The position relative to the voxels centers, and the two voxels surrounding it along each axis.
+        let mut lower = [0; 3];
+        let mut upper = [0; 3];
+        let mut fraction = [0.0; 3];
+        for axis in 0..3 {
+            let n = self.resolution[axis];
+            let relative = (point[axis] - self.min[axis]) / (self.max[axis] - self.min[axis]);
+            let position = (relative * n as f64 - 0.5).clamp(0.0, (n - 1) as f64);
+
+            lower[axis] = position.floor() as usize;
+            upper[axis] = (lower[axis] + 1).min(n - 1);
+            fraction[axis] = position - lower[axis] as f64;
+        }
+
+        let mut value = 0.0;
+        for corner in 0..8 {
+            let mut weight = 1.0;
+            let mut index = [0; 3];
+            for axis in 0..3 {
+                match corner & (1 << axis) != 0 {
+                    true => {
+                        index[axis] = upper[axis];
+                        weight *= fraction[axis];
+                    }
+                    false => {
+                        index[axis] = lower[axis];
+                        weight *= 1.0 - fraction[axis];
+                    }
+                }
+            }
+            value += weight * self.value(index[0], index[1], index[2]);
+        }
+
+        self.scale * value
+    }
+
+    fn majorant(&self) -> f64 {
+        self.scale * self.max_value
+    }
+}
+
+/// A function mapping a point to the density of a medium.
+pub type DensityFn = dyn Fn(&Point3<f64>) -> f64 + Send + Sync;
+
+/// A density computed by a user-defined function, such as a noise field.
+pub struct ProceduralDensity {
+    function: Box<DensityFn>,
+    majorant: f64,
+}
+
+impl ProceduralDensity {
+    /// Creates a density from a function of the position, which must never exceed `majorant`.
+    pub fn new(
+        function: impl Fn(&Point3<f64>) -> f64 + Send + Sync + 'static,
+        majorant: f64,
+    ) -> Self {
+        assert!(majorant > 0.0);
+
+        Self {
+            function: Box::new(function),
+            majorant,
+        }
+    }
+}
+
+impl Density for ProceduralDensity {
+    fn density(&self, point: &Point3<f64>) -> f64 {
+        (self.function)(point).clamp(0.0, self.majorant)
+    }
+
+    fn majorant(&self) -> f64 {
+        self.majorant
+    }
+}
+
+/// A participating medium whose density varies in space, such as a cloud, filling a closed `boundary`.
+/// The scattering distances are sampled by delta tracking: tentative collisions are sampled using the majorant
+/// of the density, and each one is accepted with the ratio of the actual density to the majorant.
+pub struct HeterogeneousMedium<H, D> {
+    boundary: H,
+    density: D,
+    phase_function: Arc<dyn Material>,
+}
+
+impl<H: Hittable, D: Density> HeterogeneousMedium<H, D> {
+    /// Creates a medium scattering the light with the given color.
+    pub fn new(boundary: H, density: D, albedo: Texture) -> Self {
+        Self::with_phase_function(boundary, density, Arc::new(Isotropic::new(albedo)))
+    }
+
+    /// Creates a medium scattering the light with a custom phase function.
+    pub fn with_phase_function(boundary: H, density: D, phase_function: Arc<dyn Material>) -> Self {
+        Self {
+            boundary,
+            density,
+            phase_function,
+        }
+    }
+}
+
+impl HeterogeneousMedium<BoxShape, VoxelGrid> {
+    /// Creates a medium filling the box of a voxel grid.
+    pub fn from_grid(grid: VoxelGrid, albedo: Texture) -> Self {
+        let phase_function: Arc<dyn Material> = Arc::new(Isotropic::new(albedo));
+        let (min, max) = grid.bounds();
+
+        Self::with_phase_function(
+            BoxShape::new(min, max, phase_function.clone()),
+            grid,
+            phase_function,
+        )
+    }
+}
+
+impl<H: Hittable, D: Density> Hittable for HeterogeneousMedium<H, D> {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_interval: RealInterval,
+        hit_record: &mut HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> bool {
+        let majorant = self.density.majorant();
+        if majorant <= 0.0 {
+            return false;
+        }
+
+        let Some((t_enter, t_exit)) = boundary_segment(&self.boundary, ray, t_interval, rng) else {
+            return false;
+        };
+
+        let direction_norm = ray.direction().norm();
+        let mut t = t_enter;
+        loop {
+            t += -(1.0 - rng.gen::<f64>()).ln() / (majorant * direction_norm);
+            if t >= t_exit {
+                return false;
+            }
+
+            let point = ray.origin() + t * ray.direction();
+            if rng.gen::<f64>() * majorant < self.density.density(&point) {
+                break;
+            }
+        }
+
+        if t <= t_interval.min as f64 || t_interval.max as f64 <= t {
+            return false;
+        }
+
+        record_scattering(ray, t, &self.phase_function, hit_record);
+        true
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        self.boundary.bounding_box()
+    }
+
+    fn is_bounded(&self) -> bool {
+        self.boundary.is_bounded()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csg::Csg;
+    use crate::geometry::Sphere;
+    use crate::material::Lambertian;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn sphere() -> Sphere {
+        Sphere::stationary(Point3::origin(), 1.0, Arc::new(Lambertian::default()))
+    }
+
+    fn white() -> Texture {
+        Texture::SolidColor(Vector3::new(1.0, 1.0, 1.0))
+    }
+
+    /// A 2x2x2 grid filling the box from the origin to (2, 2, 2), with the values 0 to 7.
+    fn grid() -> VoxelGrid {
+        VoxelGrid::new(
+            [2, 2, 2],
+            (0..8).map(|value| value as f32).collect(),
+            Point3::origin(),
+            Point3::new(2.0, 2.0, 2.0),
+        )
+    }
+
+    #[test]
+    fn voxel_grid_interpolation() {
+        let grid = grid();
+        // The voxels centers, and the corners of the box, take the value of a single voxel.
+        assert_eq!(grid.density(&Point3::new(0.5, 0.5, 0.5)), 0.0);
+        assert_eq!(grid.density(&Point3::new(1.5, 0.5, 0.5)), 1.0);
+        assert_eq!(grid.density(&Point3::new(0.5, 1.5, 0.5)), 2.0);
+        assert_eq!(grid.density(&Point3::new(0.5, 0.5, 1.5)), 4.0);
+        assert_eq!(grid.density(&Point3::new(2.0, 2.0, 2.0)), 7.0);
+        assert_eq!(grid.density(&Point3::origin()), 0.0);
+        // The center of the box is the average of the 8 voxels.
+        assert!((grid.density(&Point3::new(1.0, 1.0, 1.0)) - 3.5).abs() < 1e-12);
+        assert!((grid.density(&Point3::new(1.0, 0.5, 0.5)) - 0.5).abs() < 1e-12);
+        // Outside of the grid.
+        assert_eq!(grid.density(&Point3::new(2.5, 1.0, 1.0)), 0.0);
+        assert_eq!(grid.majorant(), 7.0);
+    }
+
+    #[test]
+    fn heterogeneous_medium_without_density_is_missed() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let ray = Ray::new(
+            Point3::new(0.0, 0.0, -5.0),
+            Vector3::new(0.0, 0.0, 1.0),
+            0.0,
+        );
+        let interval = RealInterval::min_max(0.001, f32::INFINITY);
+
+        let empty =
+            HeterogeneousMedium::new(sphere(), ProceduralDensity::new(|_| 0.0, 1.0), white());
+        let mut zeros = grid();
+        zeros.set_scale(0.0);
+        let zeros = HeterogeneousMedium::from_grid(zeros, white());
+        for _ in 0..100 {
+            assert!(!empty.hit(&ray, interval, &mut HitRecord::default(), &mut rng));
+            assert!(!zeros.hit(&ray, interval, &mut HitRecord::default(), &mut rng));
+        }
+    }
+
+    #[test]
+    fn dense_heterogeneous_medium_scatters_inside() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let medium =
+            HeterogeneousMedium::new(sphere(), ProceduralDensity::new(|_| 1e6, 1e6), white());
+        let interval = RealInterval::min_max(0.001, f32::INFINITY);
+
+        // From outside, and from inside after a scattering.
+        for origin in [Point3::new(0.0, 0.0, -5.0), Point3::new(0.0, 0.0, 0.5)] {
+            let ray = Ray::new(origin, Vector3::new(0.0, 0.0, 1.0), 0.0);
+            for _ in 0..100 {
+                let mut hit_record = HitRecord::default();
+                assert!(medium.hit(&ray, interval, &mut hit_record, &mut rng));
+                assert!(hit_record.hit_point.coords.norm() <= 1.0);
+                assert!((hit_record.hit_point - origin).norm() < 6.0);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "enclose a volume")]
+    fn heterogeneous_medium_is_not_a_csg_operand() {
+        let cloud = HeterogeneousMedium::from_grid(grid(), white());
+        Csg::union(cloud, sphere());
+    }
+
+    #[test]
+    #[should_panic(expected = "enclose a volume")]
+    fn constant_medium_is_not_a_csg_operand() {
+        let fog = ConstantMedium::new(
+            sphere(),
+            1.0,
+            Texture::SolidColor(Vector3::new(1.0, 1.0, 1.0)),
+        );
+        Csg::union(fog, sphere());
+    }
+}
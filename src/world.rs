@@ -1,18 +1,24 @@
 use crate::aabb::AABB;
+use crate::background::Background;
+use crate::bvh::BVHNode;
 use crate::geometry::Hittable;
-use crate::{material::Material, ray::Ray};
-use nalgebra::{Point3, Vector3};
+use crate::material::{Lambertian, Material};
+use crate::ray::{Ray, RayDifferential};
+use crate::utility::{orthonormal_basis, Basis3};
+use nalgebra::{Matrix2, Point3, Vector2, Vector3};
+use rand::RngCore;
 use real_interval::RealInterval;
+use std::sync::{Arc, OnceLock};
 
 /// Record information on the latest ray hit.
-#[derive(Default, Clone)]
+#[derive(Clone)]
 pub struct HitRecord {
     /// The point of intersection between the ray and the surface.
     pub hit_point: Point3<f64>,
     /// The normal vector of the surface hit by the ray.
     pub normal: Vector3<f64>,
     /// The material of the surface hit.
-    pub material: Material,
+    pub material: Arc<dyn Material>,
     /// The `t` value of the ray when it hit the surface.
     pub t: f64,
     /// The surface `u` coordinate of the ray-object hit point.
@@ -21,13 +27,24 @@ pub struct HitRecord {
     pub v: f64,
     /// If the surface was hit on front or back.
     pub front_face: bool,
+    /// The color interpolated from the vertices of a mesh, for meshes having colored vertices.
+    pub vertex_color: Option<Vector3<f64>>,
+    /// The derivative of the hit point with respect to `u`.
+    pub dpdu: Vector3<f64>,
+    /// The derivative of the hit point with respect to `v`.
+    pub dpdv: Vector3<f64>,
+    /// The change of the `(u, v)` coordinates between this ray and the ray one pixel to the right.
+    /// Null when the ray has no differential, in which case textures are not filtered.
+    pub duv_dx: Vector2<f64>,
+    /// The change of the `(u, v)` coordinates between this ray and the ray one pixel below.
+    pub duv_dy: Vector2<f64>,
 }
 
 impl HitRecord {
     pub fn new(
         hit_point: Point3<f64>,
         normal: Vector3<f64>,
-        material: Material,
+        material: Arc<dyn Material>,
         t: f64,
         u: f64,
         v: f64,
@@ -41,6 +58,11 @@ impl HitRecord {
             u,
             v,
             front_face,
+            vertex_color: None,
+            dpdu: Vector3::zeros(),
+            dpdv: Vector3::zeros(),
+            duv_dx: Vector2::zeros(),
+            duv_dy: Vector2::zeros(),
         }
     }
 
@@ -53,12 +75,120 @@ impl HitRecord {
             -*outward_normal
         };
     }
+
+    /// The tangent and bitangent of the shading frame: an orthonormal basis of the surface, completed by the
+    /// normal. The tangent follows `dpdu`, and the bitangent points towards increasing `v`.
+    /// Surfaces without derivatives get an arbitrary frame.
+    pub fn shading_frame(&self) -> (Vector3<f64>, Vector3<f64>) {
+        let tangent = self.dpdu - self.dpdu.dot(&self.normal) * self.normal;
+        if tangent.norm_squared() < 1e-16 {
+            return orthonormal_basis(&self.normal);
+        }
+
+        let tangent = tangent.normalize();
+        let bitangent = self.normal.cross(&tangent);
+        match bitangent.dot(&self.dpdv) < 0.0 {
+            true => (tangent, -bitangent),
+            false => (tangent, bitangent),
+        }
+    }
+
+    /// The shading frame as a basis, with the normal as `w`, to express directions relative to the surface.
+    pub(crate) fn shading_basis(&self) -> Basis3<f64> {
+        let (tangent, bitangent) = self.shading_frame();
+        Basis3::new(tangent, bitangent, self.normal)
+    }
+
+    /// The change of the hit point between this ray and the rays of the differential,
+    /// found by intersecting them with the plane tangent to the surface.
+    pub fn position_derivatives(
+        &self,
+        differential: &RayDifferential,
+    ) -> Option<(Vector3<f64>, Vector3<f64>)> {
+        let d = self.normal.dot(&self.hit_point.coords);
+        let offset_hit = |origin: &Point3<f64>, direction: &Vector3<f64>| {
+            let denominator = self.normal.dot(direction);
+            if denominator.abs() < 1e-12 {
+                return None;
+            }
+            let t = (d - self.normal.dot(&origin.coords)) / denominator;
+            Some(origin + t * direction - self.hit_point)
+        };
+
+        Some((
+            offset_hit(&differential.x_origin, &differential.x_direction)?,
+            offset_hit(&differential.y_origin, &differential.y_direction)?,
+        ))
+    }
+
+    /// Sets `duv_dx` and `duv_dy` from the differential of the ray, using `dpdu` and `dpdv`.
+    pub fn set_uv_derivatives(&mut self, differential: &RayDifferential) {
+        self.duv_dx = Vector2::zeros();
+        self.duv_dy = Vector2::zeros();
+
+        let Some((dpdx, dpdy)) = self.position_derivatives(differential) else {
+            return;
+        };
+
+        // Least squares solution of `dpdu * du + dpdv * dv = dp`.
+        let gram = Matrix2::new(
+            self.dpdu.dot(&self.dpdu),
+            self.dpdu.dot(&self.dpdv),
+            self.dpdu.dot(&self.dpdv),
+            self.dpdv.dot(&self.dpdv),
+        );
+        let Some(inverse) = gram.try_inverse() else {
+            return;
+        };
+        let solve =
+            |dp: Vector3<f64>| inverse * Vector2::new(self.dpdu.dot(&dp), self.dpdv.dot(&dp));
+
+        let (duv_dx, duv_dy) = (solve(dpdx), solve(dpdy));
+        if duv_dx
+            .iter()
+            .chain(duv_dy.iter())
+            .all(|value| value.is_finite())
+        {
+            self.duv_dx = duv_dx;
+            self.duv_dy = duv_dy;
+        }
+    }
+}
+
+impl Default for HitRecord {
+    fn default() -> Self {
+        // The default material is shared, to avoid an allocation each time a record is created.
+        static DEFAULT_MATERIAL: OnceLock<Arc<dyn Material>> = OnceLock::new();
+
+        Self {
+            hit_point: Point3::origin(),
+            normal: Vector3::zeros(),
+            material: DEFAULT_MATERIAL
+                .get_or_init(|| Arc::new(Lambertian::default()))
+                .clone(),
+            t: 0.0,
+            u: 0.0,
+            v: 0.0,
+            front_face: false,
+            vertex_color: None,
+            dpdu: Vector3::zeros(),
+            dpdv: Vector3::zeros(),
+            duv_dx: Vector2::zeros(),
+            duv_dy: Vector2::zeros(),
+        }
+    }
 }
 
 /// A wrapper of a list of hittable objects.
 pub struct World {
+    /// The objects fitting in their bounding box.
     objects: Vec<Box<dyn Hittable + Sync>>,
+    /// The objects without a finite bounding box, such as planes, which cannot be placed in a `BVHNode`.
+    unbounded: Vec<Box<dyn Hittable + Sync>>,
+    /// The bounding box of the bounded objects.
     bbox: AABB,
+    /// The light coming from the directions where no object is hit.
+    background: Background,
 }
 
 impl World {
@@ -66,28 +196,58 @@ impl World {
     pub fn empty() -> Self {
         Self {
             objects: vec![],
+            unbounded: vec![],
             bbox: AABB::default(),
+            background: Background::default(),
         }
     }
 
     /// Add a given object to the hittable list of the world, and update the bounding box correspondly.
-    pub fn add(&mut self, object: impl Hittable + 'static + Sync) {
+    /// Unbounded objects are stored separately, and do not change the bounding box.
+    /// The first bounded object replaces the default box, which would otherwise extend the bounding box to the origin.
+    pub fn add(&mut self, object: impl Hittable + 'static) {
+        if !object.is_bounded() {
+            self.unbounded.push(Box::new(object));
+            return;
+        }
+
+        self.bbox = match self.objects.is_empty() {
+            true => object.bounding_box().clone(),
+            false => AABB::from_boxes(&self.bbox, object.bounding_box()),
+        };
         self.objects.push(Box::new(object));
-        self.bbox = AABB::from_boxes(&self.bbox, self.objects.last().unwrap().bounding_box());
+    }
+
+    /// Replaces the bounded objects of the world by a single `BVHNode` containing them.
+    /// The unbounded objects are left aside, and are still tested separately.
+    pub fn build_bvh(&mut self) {
+        if self.objects.is_empty() {
+            return;
+        }
+
+        let objects = std::mem::take(&mut self.objects);
+        self.objects.push(Box::new(BVHNode::from_objects(objects)));
     }
 
     /// Check if the given ray hits any hittable from the `objects` list.
     /// If so, it adds the information of the closest hit to `hit_record`
-    pub fn hit(&self, ray: &Ray, t_interval: RealInterval, hit_record: &mut HitRecord) -> bool {
+    pub fn hit(
+        &self,
+        ray: &Ray,
+        t_interval: RealInterval,
+        hit_record: &mut HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> bool {
         let mut temporary_record = HitRecord::default();
         let mut hit_anything = false;
         let mut closest = t_interval.max as f64;
 
-        for object in self.objects.iter() {
+        for object in self.objects.iter().chain(self.unbounded.iter()) {
             if object.hit(
                 ray,
                 RealInterval::min_max(t_interval.min, closest as f32),
                 &mut temporary_record,
+                rng,
             ) {
                 hit_anything = true;
                 closest = temporary_record.t;
@@ -98,8 +258,137 @@ impl World {
         hit_anything
     }
 
-    /// Returns the list of objects contained in the World
+    /// Returns the background of the world.
+    pub fn background(&self) -> &Background {
+        &self.background
+    }
+
+    /// Replaces the background of the world, by default a sky gradient.
+    pub fn set_background(&mut self, background: Background) {
+        self.background = background;
+    }
+
+    /// Returns the list of bounded objects contained in the World
     pub fn objects(&mut self) -> &mut Vec<Box<dyn Hittable + Sync>> {
         &mut self.objects
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{Plane, Sphere};
+
+    /// The ground plane `y = 0`, and a unit sphere resting above it at `y = 2`.
+    fn world() -> (World, Arc<dyn Material>, Arc<dyn Material>) {
+        let ground: Arc<dyn Material> = Arc::new(Lambertian::default());
+        let ball: Arc<dyn Material> = Arc::new(Lambertian::default());
+
+        let mut world = World::empty();
+        world.add(Plane::new(
+            Point3::origin(),
+            Vector3::new(0.0, 1.0, 0.0),
+            ground.clone(),
+        ));
+        world.add(Sphere::stationary(
+            Point3::new(0.0, 2.0, 0.0),
+            1.0,
+            ball.clone(),
+        ));
+        world.build_bvh();
+
+        (world, ground, ball)
+    }
+
+    fn hit(world: &World, origin: Point3<f64>, direction: Vector3<f64>) -> Option<HitRecord> {
+        let ray = Ray::new(origin, direction, 0.0);
+        let mut hit_record = HitRecord::default();
+        world
+            .hit(
+                &ray,
+                RealInterval::min_max(0.001, f32::MAX),
+                &mut hit_record,
+                &mut rand::thread_rng(),
+            )
+            .then_some(hit_record)
+    }
+
+    /// Checks that the shading frame and the normal form an orthonormal basis.
+    fn assert_orthonormal(hit_record: &HitRecord) {
+        let (tangent, bitangent) = hit_record.shading_frame();
+        let normal = hit_record.normal;
+        for (a, b) in [(tangent, bitangent), (bitangent, normal), (normal, tangent)] {
+            assert!((a.norm() - 1.0).abs() < 1e-12);
+            assert!(a.dot(&b).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn shading_frame_follows_derivatives() {
+        let mut hit_record = HitRecord {
+            normal: Vector3::new(0.0, 0.0, 1.0),
+            dpdu: Vector3::new(2.0, 0.0, 0.5),
+            ..Default::default()
+        };
+
+        // The bitangent points towards increasing `v`, on either side of the tangent.
+        for dpdv in [Vector3::new(0.3, 1.0, 0.0), Vector3::new(0.3, -1.0, 0.0)] {
+            hit_record.dpdv = dpdv;
+            assert_orthonormal(&hit_record);
+
+            let (tangent, bitangent) = hit_record.shading_frame();
+            assert!((tangent - Vector3::new(1.0, 0.0, 0.0)).norm() < 1e-12);
+            assert!((bitangent - Vector3::new(0.0, dpdv.y, 0.0)).norm() < 1e-12);
+        }
+
+        // Without derivatives, the frame is arbitrary but still orthonormal.
+        hit_record.dpdu = Vector3::zeros();
+        hit_record.dpdv = Vector3::zeros();
+        assert_orthonormal(&hit_record);
+    }
+
+    #[test]
+    fn plane_is_kept_out_of_bvh() {
+        let (world, _, _) = world();
+        assert_eq!(world.objects.len(), 1);
+        assert_eq!(world.unbounded.len(), 1);
+
+        for (axis, (min, max)) in [(-1.0, 1.0), (1.0, 3.0), (-1.0, 1.0)]
+            .into_iter()
+            .enumerate()
+        {
+            let interval = world.bbox.axis(axis);
+            assert!(
+                (interval.min as f64 - min).abs() < 1e-3,
+                "{axis}: {interval:?}"
+            );
+            assert!(
+                (interval.max as f64 - max).abs() < 1e-3,
+                "{axis}: {interval:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn closest_hit_across_objects() {
+        let (world, ground, ball) = world();
+        let down = Vector3::new(0.0, -1.0, 0.0);
+
+        // From above, the sphere is in front of the plane.
+        let hit_record = hit(&world, Point3::new(0.0, 5.0, 0.0), down).unwrap();
+        assert!((hit_record.t - 2.0).abs() < 1e-9);
+        assert!(Arc::ptr_eq(&hit_record.material, &ball));
+
+        // From below, the plane is in front of the sphere.
+        let hit_record = hit(&world, Point3::new(0.0, -5.0, 0.0), -down).unwrap();
+        assert!((hit_record.t - 5.0).abs() < 1e-9);
+        assert!(Arc::ptr_eq(&hit_record.material, &ground));
+
+        // Beside the sphere, only the plane is hit.
+        let hit_record = hit(&world, Point3::new(3.0, 5.0, 0.0), down).unwrap();
+        assert!((hit_record.t - 5.0).abs() < 1e-9);
+        assert!(Arc::ptr_eq(&hit_record.material, &ground));
+
+        assert!(hit(&world, Point3::new(3.0, 5.0, 0.0), -down).is_none());
+    }
+}